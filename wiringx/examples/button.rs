@@ -0,0 +1,18 @@
+//! Prints the gestures performed on a push button.
+
+use wiringx::{
+    gpio::{Button, ButtonConfig, Input},
+    platform::Platform,
+    WiringX,
+};
+
+fn main() {
+    let wiringx = WiringX::new(Platform::MilkVDuoS).unwrap();
+
+    let pin = wiringx.gpio_pin::<Input>(0).unwrap();
+    let button = Button::new(pin, ButtonConfig::default()).unwrap();
+
+    for event in button.events() {
+        println!("{event:?}");
+    }
+}
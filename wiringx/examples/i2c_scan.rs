@@ -0,0 +1,32 @@
+//! Lists all devices responding on an I2C bus, like `i2cdetect`.
+
+use wiringx::{platform::Platform, WiringX};
+
+use std::path::PathBuf;
+
+const USAGE: &str = "
+Usage: i2c_scan platform bus
+Example: i2c_scan milkv_duos /dev/i2c-1
+";
+
+fn main() {
+    let mut args = std::env::args();
+    args.next();
+
+    let (Some(platform), Some(bus)) = (args.next(), args.next()) else {
+        eprintln!("{USAGE}");
+        std::process::exit(-1);
+    };
+
+    let platform = Platform::from_string(&platform).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(-1);
+    });
+
+    let wiringx = WiringX::new(platform).unwrap();
+    let bus = wiringx.setup_i2c_bus(PathBuf::from(bus)).unwrap();
+
+    for addr in bus.scan().unwrap() {
+        println!("Found device at {addr}");
+    }
+}
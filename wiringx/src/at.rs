@@ -0,0 +1,618 @@
+//! AT command engine for modems and radio modules on a UART.
+
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{uart::Uart, WiringXError};
+
+/// Unsolicited result codes not starting with `+`, which are told apart from responses by prefix.
+const DEFAULT_URC_PREFIXES: [&str; 6] = [
+    "RING",
+    "RDY",
+    "Call Ready",
+    "SMS Ready",
+    "NORMAL POWER DOWN",
+    "UNDER-VOLTAGE",
+];
+
+/// Final result codes completing a command successfully.
+const SUCCESS_RESULTS: [&str; 3] = ["OK", "SEND OK", "CONNECT"];
+
+/// Final result codes completing a command with a failure.
+const FAILURE_RESULTS: [&str; 6] = [
+    "ERROR",
+    "SEND FAIL",
+    "NO CARRIER",
+    "BUSY",
+    "NO ANSWER",
+    "NO DIALTONE",
+];
+
+/// Longest line kept, longer ones get split.
+const MAX_LINE_LENGTH: usize = 1024;
+
+/// Errors of AT commands.
+#[derive(Debug, Error)]
+pub enum AtError {
+    /// The command did not complete in time.
+    #[error("The AT command did not complete in time.")]
+    Timeout,
+    /// The module answered with `ERROR`.
+    #[error("The AT command failed.")]
+    Error,
+    /// The module answered with `+CME ERROR`, the code or message being given.
+    #[error("The AT command failed with CME error {0}.")]
+    Cme(String),
+    /// The module answered with `+CMS ERROR`, the code or message being given.
+    #[error("The AT command failed with CMS error {0}.")]
+    Cms(String),
+    /// The module answered with another failing result code, like `NO CARRIER` or `SEND FAIL`.
+    #[error("The AT command failed with {0}.")]
+    Failed(String),
+    /// The UART failed.
+    #[error("UART error: {0}")]
+    Uart(WiringXError),
+}
+
+impl From<WiringXError> for AtError {
+    fn from(error: WiringXError) -> Self {
+        Self::Uart(error)
+    }
+}
+
+/// What was received from the module.
+enum Received {
+    Line(String),
+    /// The `>` prompt asking for a payload.
+    Prompt,
+}
+
+/// Kinds of lines received while a command runs.
+enum Line {
+    Final(Result<(), AtError>),
+    Response(String),
+    /// The echo or an unsolicited result code.
+    Consumed,
+}
+
+/// Sends AT commands to a module on a UART and collects their responses.
+///
+/// Lines received while no command runs, lines starting with `+NAME:` where `NAME` is not the name of the running
+/// command and lines starting with a registered prefix are unsolicited result codes. They get sent to the receiver
+/// returned by `AtClient::new` instead of becoming part of a response.
+///
+/// Late lines of a timed out command get handed to that receiver as well when the next command is sent, except for
+/// final result codes, which get dropped.
+///
+/// Works with any UART, including pseudo terminals, which the tests use to play the module from a script.
+#[derive(Debug)]
+pub struct AtClient {
+    uart: Uart,
+    buffer: Vec<u8>,
+    urc_prefixes: Vec<String>,
+    urcs: Sender<String>,
+}
+
+impl AtClient {
+    /// Creates a client for the module on the UART, returning it with the receiver of unsolicited result codes.
+    ///
+    /// Command echo should be disabled with `ATE0`, although echoed commands get skipped.
+    pub fn new(uart: Uart) -> (Self, Receiver<String>) {
+        let (urcs, receiver) = mpsc::channel();
+
+        let client = Self {
+            uart,
+            buffer: Vec::new(),
+            urc_prefixes: DEFAULT_URC_PREFIXES.map(String::from).to_vec(),
+            urcs,
+        };
+
+        (client, receiver)
+    }
+
+    /// Marks lines starting with the prefix as unsolicited result codes, for example `0, CLOSED`.
+    pub fn add_urc_prefix(&mut self, prefix: impl Into<String>) {
+        self.urc_prefixes.push(prefix.into());
+    }
+
+    /// Returns the UART of the module.
+    pub fn uart(&self) -> &Uart {
+        &self.uart
+    }
+
+    /// Returns the UART, consuming the client.
+    pub fn into_inner(self) -> Uart {
+        self.uart
+    }
+
+    /// Sends the command, like `AT+CSQ`, and returns the lines of its response without the final result code.
+    pub fn command(&mut self, command: &str, timeout: Duration) -> Result<Vec<String>, AtError> {
+        let deadline = Instant::now() + timeout;
+
+        self.send(command)?;
+        let result = self.collect(command, deadline);
+
+        self.finish(result)
+    }
+
+    /// Sends the command, waits for the `>` prompt and sends the payload, for example for `AT+CIPSEND=5`.
+    ///
+    /// Returns the lines of the response following the payload without the final result code, like `SEND OK`.
+    /// Payloads ended by Ctrl-Z, like SMS texts, have to contain the `0x1a` byte themselves.
+    pub fn command_with_payload(
+        &mut self,
+        command: &str,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<String>, AtError> {
+        let deadline = Instant::now() + timeout;
+
+        self.send(command)?;
+
+        let result = self.prompt(command, deadline).and_then(|()| {
+            self.uart.write(payload)?;
+            self.collect(command, deadline)
+        });
+
+        self.finish(result)
+    }
+
+    /// Waits up to the timeout for unsolicited result codes, returning how many were received.
+    pub fn poll_urcs(&mut self, timeout: Duration) -> Result<usize, AtError> {
+        let deadline = Instant::now() + timeout;
+        let mut count = 0;
+
+        loop {
+            match self.receive(deadline, false) {
+                Ok(Received::Line(line)) => {
+                    let _ = self.urcs.send(line);
+                    count += 1;
+                }
+                Ok(Received::Prompt) => {}
+                Err(AtError::Timeout) => return Ok(count),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Sends the command, after clearing out what is left of earlier commands.
+    fn send(&mut self, command: &str) -> Result<(), AtError> {
+        self.discard_stale()?;
+        self.uart.write(format!("{command}\r").as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Empties the input before a command, so late responses of timed out commands do not get mixed into its response.
+    ///
+    /// Complete lines are unsolicited result codes unless they are final result codes, partial lines get dropped.
+    fn discard_stale(&mut self) -> Result<(), AtError> {
+        let mut chunk = [0; 256];
+
+        loop {
+            let read = self.uart.read(&mut chunk, Duration::ZERO)?;
+            if read == 0 {
+                break;
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+
+        let complete = self
+            .buffer
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |end| end + 1);
+
+        for line in self.buffer[..complete].split(|&byte| byte == b'\n') {
+            let line = String::from_utf8_lossy(line).trim().to_string();
+
+            if !line.is_empty() && final_result(&line).is_none() {
+                let _ = self.urcs.send(line);
+            }
+        }
+
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    /// Forgets the partial line of a timed out command, its rest would only get mistaken for a response later.
+    fn finish<T>(&mut self, result: Result<T, AtError>) -> Result<T, AtError> {
+        if matches!(result, Err(AtError::Timeout)) {
+            self.buffer.clear();
+        }
+
+        result
+    }
+
+    /// Waits for the `>` prompt asking for the payload.
+    fn prompt(&mut self, command: &str, deadline: Instant) -> Result<(), AtError> {
+        loop {
+            match self.receive(deadline, true)? {
+                Received::Prompt => return Ok(()),
+                Received::Line(line) => {
+                    // Nothing but a failure may complete the command before the payload.
+                    if let Line::Final(result) = self.classify(command, line) {
+                        result?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Collects response lines until a final result code.
+    fn collect(&mut self, command: &str, deadline: Instant) -> Result<Vec<String>, AtError> {
+        let mut lines = Vec::new();
+
+        loop {
+            let Received::Line(line) = self.receive(deadline, false)? else {
+                continue;
+            };
+
+            match self.classify(command, line) {
+                Line::Final(result) => return result.map(|()| lines),
+                Line::Response(line) => lines.push(line),
+                Line::Consumed => {}
+            }
+        }
+    }
+
+    /// Tells final result codes and response lines apart, routing unsolicited result codes and skipping the echo.
+    fn classify(&self, command: &str, line: String) -> Line {
+        if let Some(result) = final_result(&line) {
+            return Line::Final(result);
+        }
+
+        if line.eq_ignore_ascii_case(command.trim()) {
+            return Line::Consumed;
+        }
+
+        if self.is_urc(command, &line) {
+            let _ = self.urcs.send(line);
+            return Line::Consumed;
+        }
+
+        Line::Response(line)
+    }
+
+    /// Returns whether the line is an unsolicited result code rather than a response to the command.
+    fn is_urc(&self, command: &str, line: &str) -> bool {
+        if self
+            .urc_prefixes
+            .iter()
+            .any(|prefix| line.starts_with(prefix.as_str()))
+        {
+            return true;
+        }
+
+        // Responses of extended commands start with the name of the command, like `+CSQ: 20,0`.
+        match name(line) {
+            Some(name) if line[name.len()..].starts_with(':') => !command
+                .get(2..)
+                .and_then(self::name)
+                .is_some_and(|command| command.eq_ignore_ascii_case(name)),
+            _ => false,
+        }
+    }
+
+    /// Reads the next non-empty line, or the prompt if expected.
+    fn receive(&mut self, deadline: Instant, prompt: bool) -> Result<Received, AtError> {
+        let mut chunk = [0; 256];
+
+        loop {
+            // Line endings left over from the previous line precede the prompt.
+            let start = self
+                .buffer
+                .iter()
+                .position(|&byte| byte != b'\r' && byte != b'\n')
+                .unwrap_or(self.buffer.len());
+            self.buffer.drain(..start);
+
+            if prompt && self.buffer.first() == Some(&b'>') {
+                let len = if self.buffer.get(1) == Some(&b' ') {
+                    2
+                } else {
+                    1
+                };
+                self.buffer.drain(..len);
+
+                return Ok(Received::Prompt);
+            }
+
+            let end = self.buffer.iter().position(|&byte| byte == b'\n');
+
+            if let Some(end) =
+                end.or((self.buffer.len() > MAX_LINE_LENGTH).then_some(MAX_LINE_LENGTH))
+            {
+                let line: Vec<u8> = self.buffer.drain(..end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+
+                if !line.is_empty() {
+                    return Ok(Received::Line(line));
+                }
+
+                continue;
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let read = self.uart.read(&mut chunk, remaining)?;
+
+            if read == 0 {
+                return Err(AtError::Timeout);
+            }
+
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+/// Returns the outcome of the command if the line is a final result code.
+fn final_result(line: &str) -> Option<Result<(), AtError>> {
+    if SUCCESS_RESULTS.contains(&line) {
+        return Some(Ok(()));
+    }
+    if FAILURE_RESULTS.contains(&line) {
+        return Some(Err(match line {
+            "ERROR" => AtError::Error,
+            _ => AtError::Failed(line.to_string()),
+        }));
+    }
+    if let Some(code) = line.strip_prefix("+CME ERROR:") {
+        return Some(Err(AtError::Cme(code.trim().to_string())));
+    }
+    if let Some(code) = line.strip_prefix("+CMS ERROR:") {
+        return Some(Err(AtError::Cms(code.trim().to_string())));
+    }
+
+    None
+}
+
+/// Returns the extended command name, like `+CSQ`, at the start of the text.
+fn name(text: &str) -> Option<&str> {
+    let mut chars = text.char_indices();
+
+    match chars.next() {
+        Some((_, '+' | '^' | '$' | '#' | '%')) => {}
+        _ => return None,
+    }
+
+    let end = chars
+        .find(|(_, char)| !char.is_ascii_alphanumeric())
+        .map_or(text.len(), |(index, _)| index);
+
+    Some(&text[..end]).filter(|name| name.len() > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::uart::{pty_pair, FlowControl, Parity, SerialConfig, StopBits};
+    use Step::{Expect, Reply, Wait};
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// What the scripted module does next.
+    enum Step {
+        /// Waits for exactly these bytes from the client.
+        Expect(&'static str),
+        /// Sends the text to the client.
+        Reply(&'static str),
+        /// Does nothing for a while.
+        Wait(Duration),
+    }
+
+    /// Runs the test with a client talking to a module following the script on the other side of a pseudo terminal.
+    fn with_module(script: &[Step], test: impl FnOnce(&mut AtClient, &Receiver<String>)) {
+        let (client, module) = pty_pair(SerialConfig {
+            baud_rate: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        });
+        let (mut client, urcs) = AtClient::new(client);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for step in script {
+                    match step {
+                        Expect(expected) => {
+                            let mut received = vec![0; expected.len()];
+                            let mut len = 0;
+
+                            while len < received.len() {
+                                let read = module.read(&mut received[len..], TIMEOUT).unwrap();
+                                assert_ne!(read, 0, "timed out waiting for {expected:?}");
+                                len += read;
+                            }
+
+                            assert_eq!(String::from_utf8_lossy(&received), *expected);
+                        }
+                        Reply(text) => module.write(text.as_bytes()).unwrap(),
+                        Wait(duration) => thread::sleep(*duration),
+                    }
+                }
+            });
+
+            test(&mut client, &urcs);
+        });
+    }
+
+    #[test]
+    fn multi_line_response() {
+        with_module(
+            &[
+                Expect("ATI\r"),
+                Reply("\r\nQuectel\r\nEC25\r\nRevision: EC25EFAR06A03M4G\r\n\r\nOK\r\n"),
+            ],
+            |client, _| {
+                assert_eq!(
+                    client.command("ATI", TIMEOUT).unwrap(),
+                    ["Quectel", "EC25", "Revision: EC25EFAR06A03M4G"]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn failures() {
+        with_module(
+            &[
+                Expect("AT+FOO\r"),
+                Reply("\r\nERROR\r\n"),
+                Expect("AT+CPIN?\r"),
+                Reply("\r\n+CME ERROR: 10\r\n"),
+                Expect("AT+CMGR=7\r"),
+                Reply("\r\n+CMS ERROR: 321\r\n"),
+                Expect("ATD+31612345678;\r"),
+                Reply("\r\nNO CARRIER\r\n"),
+            ],
+            |client, _| {
+                assert!(matches!(
+                    client.command("AT+FOO", TIMEOUT),
+                    Err(AtError::Error)
+                ));
+                assert!(matches!(
+                    client.command("AT+CPIN?", TIMEOUT),
+                    Err(AtError::Cme(code)) if code == "10"
+                ));
+                assert!(matches!(
+                    client.command("AT+CMGR=7", TIMEOUT),
+                    Err(AtError::Cms(code)) if code == "321"
+                ));
+                assert!(matches!(
+                    client.command("ATD+31612345678;", TIMEOUT),
+                    Err(AtError::Failed(result)) if result == "NO CARRIER"
+                ));
+            },
+        );
+    }
+
+    #[test]
+    fn skips_echo() {
+        with_module(
+            &[
+                Expect("AT+CSQ\r"),
+                Reply("AT+CSQ\r\r\n+CSQ: 20,0\r\n\r\nOK\r\n"),
+            ],
+            |client, urcs| {
+                assert_eq!(client.command("AT+CSQ", TIMEOUT).unwrap(), ["+CSQ: 20,0"]);
+                assert!(urcs.try_recv().is_err());
+            },
+        );
+    }
+
+    #[test]
+    fn routes_urcs_during_commands() {
+        with_module(
+            &[
+                Expect("AT+CSQ\r"),
+                Reply("\r\nRING\r\n\r\n+CSQ: 20,0\r\n\r\n+CMTI: \"SM\",3\r\n\r\nOK\r\n"),
+            ],
+            |client, urcs| {
+                assert_eq!(client.command("AT+CSQ", TIMEOUT).unwrap(), ["+CSQ: 20,0"]);
+                assert_eq!(urcs.try_recv().unwrap(), "RING");
+                assert_eq!(urcs.try_recv().unwrap(), "+CMTI: \"SM\",3");
+            },
+        );
+    }
+
+    #[test]
+    fn routes_urcs_between_commands() {
+        with_module(
+            &[
+                Reply("\r\n+CMTI: \"SM\",4\r\n\r\nRING\r\n\r\n0, CLOSED\r\n"),
+                Expect("AT\r"),
+                Reply("\r\nOK\r\n"),
+            ],
+            |client, urcs| {
+                client.add_urc_prefix("0, CLOSED");
+
+                assert_eq!(client.poll_urcs(Duration::from_millis(100)).unwrap(), 3);
+                assert_eq!(
+                    urcs.try_iter().collect::<Vec<_>>(),
+                    ["+CMTI: \"SM\",4", "RING", "0, CLOSED"]
+                );
+
+                assert!(client.command("AT", TIMEOUT).unwrap().is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn payload_after_prompt() {
+        with_module(
+            &[
+                Expect("AT+CMGS=\"+31612345678\"\r"),
+                Reply("\r\n> "),
+                Expect("Hello\x1a"),
+                Reply("\r\n+CMGS: 12\r\n\r\nOK\r\n"),
+                Expect("AT+CIPSEND=5\r"),
+                Reply("\r\n+CME ERROR: 3\r\n"),
+            ],
+            |client, _| {
+                assert_eq!(
+                    client
+                        .command_with_payload("AT+CMGS=\"+31612345678\"", b"Hello\x1a", TIMEOUT)
+                        .unwrap(),
+                    ["+CMGS: 12"]
+                );
+                assert!(matches!(
+                    client.command_with_payload("AT+CIPSEND=5", b"hello", TIMEOUT),
+                    Err(AtError::Cme(code)) if code == "3"
+                ));
+            },
+        );
+    }
+
+    #[test]
+    fn late_response_after_timeout() {
+        with_module(
+            &[
+                Expect("AT+COPS=?\r"),
+                Reply("\r\n+COPS: (2,\"KPN\")"),
+                Wait(Duration::from_millis(150)),
+                Reply(",(1,\"Vodafone\")\r\n\r\nERROR\r\n\r\nRING\r\n"),
+                Expect("AT+CSQ\r"),
+                Reply("\r\n+CSQ: 20,0\r\n\r\nOK\r\n"),
+            ],
+            |client, urcs| {
+                assert!(matches!(
+                    client.command("AT+COPS=?", Duration::from_millis(100)),
+                    Err(AtError::Timeout)
+                ));
+
+                // The late response of the timed out command arrives before the next command gets sent.
+                thread::sleep(Duration::from_millis(150));
+
+                assert_eq!(client.command("AT+CSQ", TIMEOUT).unwrap(), ["+CSQ: 20,0"]);
+                assert_eq!(
+                    urcs.try_iter().collect::<Vec<_>>(),
+                    [",(1,\"Vodafone\")", "RING"]
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn times_out() {
+        with_module(
+            &[Expect("AT+COPS=?\r"), Reply("\r\n+COPS: (2,\"KPN\")")],
+            |client, _| {
+                let start = Instant::now();
+
+                assert!(matches!(
+                    client.command("AT+COPS=?", Duration::from_millis(100)),
+                    Err(AtError::Timeout)
+                ));
+                assert!(start.elapsed() >= Duration::from_millis(100));
+            },
+        );
+    }
+}
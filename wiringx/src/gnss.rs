@@ -0,0 +1,404 @@
+//! NMEA 0183 driver for GNSS receivers on a UART.
+
+mod command;
+pub mod nmea;
+
+pub use command::CommandSet;
+pub use nmea::{Date, FixMode, FixQuality, Position, Satellite, Sentence, Talker, UtcTime};
+
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::{uart::Uart, WiringXError};
+
+/// Longest line kept, sentences are at most 82 characters long.
+const MAX_LINE_LENGTH: usize = 256;
+
+/// Knots in meters per second.
+const KNOT: f32 = 0.514_444;
+
+/// Errors of GNSS receivers.
+#[derive(Debug, Error)]
+pub enum GnssError {
+    /// No complete sentence arrived in time.
+    #[error("The GNSS receiver did not send a sentence in time.")]
+    Timeout,
+    /// A sentence was received with a wrong checksum.
+    #[error("Received an NMEA sentence with an invalid checksum.")]
+    Checksum,
+    /// A sentence is malformed.
+    #[error("Received an invalid NMEA sentence.")]
+    InvalidSentence,
+    /// A command can not be sent, for example because of an out of range value.
+    #[error("The GNSS command is not valid.")]
+    InvalidCommand,
+    /// The sentence type is not supported.
+    #[error("The NMEA sentence type is not supported.")]
+    Unsupported,
+    /// The UART failed.
+    #[error("UART error: {0}")]
+    Uart(WiringXError),
+}
+
+impl From<WiringXError> for GnssError {
+    fn from(error: WiringXError) -> Self {
+        Self::Uart(error)
+    }
+}
+
+/// Navigation state collected from the sentences of one or more epochs.
+///
+/// Values not sent by the receiver stay `None`. The satellites in view get replaced once all GSV sentences of a
+/// system have arrived.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Fix {
+    pub time: Option<UtcTime>,
+    pub date: Option<Date>,
+    pub position: Option<Position>,
+    /// Altitude above mean sea level in meters.
+    pub altitude: Option<f32>,
+    pub quality: FixQuality,
+    pub mode: FixMode,
+    /// Number of satellites used for the fix.
+    pub satellites_used: Option<u8>,
+    pub pdop: Option<f32>,
+    pub hdop: Option<f32>,
+    pub vdop: Option<f32>,
+    /// Speed over ground in meters per second.
+    pub speed: Option<f32>,
+    /// Course over ground in degrees from true north.
+    pub course: Option<f32>,
+    pub satellites: Vec<Satellite>,
+}
+
+impl Fix {
+    /// Returns whether the receiver has a position fix.
+    pub fn is_valid(&self) -> bool {
+        self.quality != FixQuality::Invalid && self.position.is_some()
+    }
+}
+
+/// GNSS receiver sending NMEA sentences on a UART.
+#[derive(Debug)]
+pub struct Gnss {
+    uart: Uart,
+    buffer: Vec<u8>,
+    fix: Fix,
+    /// Time of the epoch being collected and whether its GGA and RMC sentences have arrived.
+    epoch: Option<UtcTime>,
+    seen_gga: bool,
+    seen_rmc: bool,
+    /// Sentence belonging to the next epoch, received while completing the previous one.
+    stashed: Option<(Talker, Sentence)>,
+    /// Satellites of the GSV sentences of a system received so far.
+    satellites: Vec<Satellite>,
+}
+
+impl Gnss {
+    /// Creates a driver for the receiver on the UART, which has to be configured already.
+    pub fn new(uart: Uart) -> Self {
+        Self {
+            uart,
+            buffer: Vec::with_capacity(MAX_LINE_LENGTH),
+            fix: Fix::default(),
+            epoch: None,
+            seen_gga: false,
+            seen_rmc: false,
+            stashed: None,
+            satellites: Vec::new(),
+        }
+    }
+
+    /// Returns the UART of the receiver.
+    pub fn uart(&self) -> &Uart {
+        &self.uart
+    }
+
+    /// Returns the UART, consuming the driver.
+    pub fn into_inner(self) -> Uart {
+        self.uart
+    }
+
+    /// Returns the state collected from all sentences so far.
+    pub fn fix(&self) -> &Fix {
+        &self.fix
+    }
+
+    /// Waits up to the timeout for the next line starting with `$`, without the line ending.
+    pub fn read_line(&mut self, timeout: Duration) -> Result<String, GnssError> {
+        let deadline = Instant::now() + timeout;
+        let mut chunk = [0; 128];
+
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+
+                // Bytes before the start of a sentence are leftovers of binary protocols or noise.
+                let Some(start) = line.iter().position(|&byte| byte == b'$') else {
+                    continue;
+                };
+
+                return String::from_utf8(line[start..].to_vec())
+                    .map(|line| line.trim_end().to_string())
+                    .map_err(|_| GnssError::InvalidSentence);
+            }
+
+            if self.buffer.len() > MAX_LINE_LENGTH {
+                self.buffer.clear();
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let read = self.uart.read(&mut chunk, remaining)?;
+
+            if read == 0 {
+                return Err(GnssError::Timeout);
+            }
+
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Waits up to the timeout for the next supported sentence, skipping other ones.
+    ///
+    /// The sentence also gets applied to the state returned by `fix`.
+    pub fn read_sentence(&mut self, timeout: Duration) -> Result<(Talker, Sentence), GnssError> {
+        let (talker, sentence) = self.next_parsed(timeout)?;
+        self.apply(talker, &sentence);
+
+        Ok((talker, sentence))
+    }
+
+    /// Waits up to the timeout for the end of the current epoch and returns the collected state.
+    ///
+    /// An epoch ends once its GGA and RMC sentences have arrived, or a sentence with a newer time starts the next one.
+    /// Broken sentences get skipped.
+    pub fn next_fix(&mut self, timeout: Duration) -> Result<Fix, GnssError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let (talker, sentence) = match self.stashed.take() {
+                Some(stashed) => stashed,
+                None => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+
+                    match self.next_parsed(remaining) {
+                        Ok(parsed) => parsed,
+                        Err(GnssError::Checksum | GnssError::InvalidSentence) => continue,
+                        Err(error) => return Err(error),
+                    }
+                }
+            };
+
+            let time = match &sentence {
+                Sentence::Gga(gga) => gga.time,
+                Sentence::Rmc(rmc) => rmc.time,
+                _ => None,
+            };
+
+            if let (Some(time), Some(epoch)) = (time, self.epoch) {
+                if time != epoch {
+                    self.stashed = Some((talker, sentence));
+                    self.end_epoch();
+                    return Ok(self.fix.clone());
+                }
+            }
+
+            self.apply(talker, &sentence);
+
+            if self.seen_gga && self.seen_rmc {
+                self.end_epoch();
+                return Ok(self.fix.clone());
+            }
+        }
+    }
+
+    /// Reads and parses the next supported sentence without applying it.
+    fn next_parsed(&mut self, timeout: Duration) -> Result<(Talker, Sentence), GnssError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            match nmea::parse(&self.read_line(remaining)?) {
+                Err(GnssError::Unsupported) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn end_epoch(&mut self) {
+        self.epoch = None;
+        self.seen_gga = false;
+        self.seen_rmc = false;
+    }
+
+    /// Updates the collected state with the sentence.
+    fn apply(&mut self, talker: Talker, sentence: &Sentence) {
+        let fix = &mut self.fix;
+
+        match sentence {
+            Sentence::Gga(gga) => {
+                fix.time = gga.time;
+                fix.position = gga.position;
+                fix.altitude = gga.altitude;
+                fix.quality = gga.quality;
+                fix.satellites_used = gga.satellites;
+                fix.hdop = gga.hdop;
+
+                self.epoch = gga.time.or(self.epoch);
+                self.seen_gga = true;
+            }
+            Sentence::Rmc(rmc) => {
+                fix.time = rmc.time;
+                fix.date = rmc.date;
+                fix.position = rmc.position.filter(|_| rmc.valid);
+                fix.speed = rmc.speed_knots.map(|speed| speed * KNOT);
+                fix.course = rmc.course;
+
+                self.epoch = rmc.time.or(self.epoch);
+                self.seen_rmc = true;
+            }
+            Sentence::Gsa(gsa) => {
+                fix.mode = gsa.mode;
+                fix.pdop = gsa.pdop;
+                fix.hdop = gsa.hdop;
+                fix.vdop = gsa.vdop;
+            }
+            Sentence::Gsv(gsv) => {
+                if gsv.number == 1 {
+                    self.satellites.clear();
+                }
+
+                self.satellites.extend_from_slice(&gsv.satellites);
+
+                if gsv.number == gsv.total {
+                    fix.satellites
+                        .retain(|satellite| satellite.system != talker);
+                    fix.satellites.append(&mut self.satellites);
+                }
+            }
+            Sentence::Vtg(vtg) => {
+                fix.speed = vtg
+                    .speed_kmh
+                    .map(|speed| speed / 3.6)
+                    .or(vtg.speed_knots.map(|speed| speed * KNOT));
+                fix.course = vtg.course;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart::{pty_pair, FlowControl, Parity, SerialConfig, StopBits};
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Returns the driver with the receiver side of a pseudo terminal, having sent the lines.
+    fn receiver(lines: &[&str]) -> (Gnss, Uart) {
+        let (uart, receiver) = pty_pair(SerialConfig {
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        });
+
+        for line in lines {
+            receiver.write(format!("{line}\r\n").as_bytes()).unwrap();
+        }
+
+        (Gnss::new(uart), receiver)
+    }
+
+    #[test]
+    fn aggregates_epochs() {
+        let (mut gnss, _receiver) = receiver(&[
+            "$GNGGA,000000.50,0000.0000,N,07400.6000,W,1,05,1.5,10.0,M,-34.2,M,,*78",
+            "$GNGSA,A,3,10,12,,,,,,,,,,,1.8,1.5,1.0*22",
+            // Broken sentences get skipped.
+            "$GNGSA,A,3,10,12,,,,,,,,,,,1.8,1.5,1.0*23",
+            "$PMTK001,220,3*30",
+            "$GNRMC,000000.50,A,0000.0000,N,07400.6000,W,1.0,90.0,010124,,,A*6C",
+            "$GNGGA,000001.50,0000.0000,N,07400.6000,W,1,05,1.5,11.0,M,-34.2,M,,*78",
+            "$GNRMC,000001.50,A,0000.0000,N,07400.6000,W,1.0,90.0,010124,,,A*6D",
+        ]);
+
+        let fix = gnss.next_fix(TIMEOUT).unwrap();
+        assert!(fix.is_valid());
+        assert_eq!(fix.time.unwrap().second, 0);
+        assert_eq!(fix.altitude, Some(10.0));
+        assert_eq!(fix.mode, FixMode::Fix3d);
+        assert_eq!(fix.pdop, Some(1.8));
+        assert_eq!(fix.course, Some(90.0));
+        assert_eq!(fix.speed, Some(KNOT));
+        assert_eq!(
+            fix.date,
+            Some(Date {
+                year: 2024,
+                month: 1,
+                day: 1
+            })
+        );
+
+        let fix = gnss.next_fix(TIMEOUT).unwrap();
+        assert_eq!(fix.time.unwrap().second, 1);
+        assert_eq!(fix.altitude, Some(11.0));
+        // Kept from the previous epoch, as no GSA was sent.
+        assert_eq!(fix.mode, FixMode::Fix3d);
+
+        assert!(matches!(
+            gnss.next_fix(Duration::from_millis(50)),
+            Err(GnssError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn newer_time_ends_epoch() {
+        // The RMC sentence of the first epoch is missing.
+        let (mut gnss, _receiver) = receiver(&[
+            "$GNGGA,000000.50,0000.0000,N,07400.6000,W,1,05,1.5,10.0,M,-34.2,M,,*78",
+            "$GNGGA,000001.50,0000.0000,N,07400.6000,W,1,05,1.5,11.0,M,-34.2,M,,*78",
+            "$GNRMC,000001.50,A,0000.0000,N,07400.6000,W,1.0,90.0,010124,,,A*6D",
+        ]);
+
+        let fix = gnss.next_fix(TIMEOUT).unwrap();
+        assert_eq!(fix.time.unwrap().second, 0);
+        assert_eq!(fix.altitude, Some(10.0));
+        assert_eq!(fix.date, None);
+
+        let fix = gnss.next_fix(TIMEOUT).unwrap();
+        assert_eq!(fix.time.unwrap().second, 1);
+        assert_eq!(fix.altitude, Some(11.0));
+        assert!(fix.date.is_some());
+    }
+
+    #[test]
+    fn collects_satellites_per_system() {
+        let (mut gnss, _receiver) = receiver(&[
+            "$GPGSV,2,1,08,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,45*75",
+            "$GPGSV,2,2,08,15,10,050,,18,65,280,37,22,33,120,40,25,05,015,*74",
+            "$GLGSV,1,1,02,70,45,100,30,71,20,200,*65",
+            "$GNGGA,000000.50,0000.0000,N,07400.6000,W,1,05,1.5,10.0,M,-34.2,M,,*78",
+            "$GNRMC,000000.50,A,0000.0000,N,07400.6000,W,1.0,90.0,010124,,,A*6C",
+            "$GLGSV,1,1,02,70,45,100,30,71,20,200,*65",
+        ]);
+
+        let fix = gnss.next_fix(TIMEOUT).unwrap();
+        let count = |system| {
+            fix.satellites
+                .iter()
+                .filter(|satellite| satellite.system == system)
+                .count()
+        };
+        assert_eq!(count(Talker::Gps), 8);
+        assert_eq!(count(Talker::Glonass), 2);
+        assert_eq!(fix.satellites[7].snr, None);
+
+        // A repeated system replaces its satellites instead of adding to them.
+        gnss.read_sentence(TIMEOUT).unwrap();
+        assert_eq!(gnss.fix().satellites.len(), 10);
+    }
+}
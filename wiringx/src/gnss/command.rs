@@ -0,0 +1,266 @@
+//! Configuration commands of GNSS receivers.
+
+use std::time::Duration;
+
+use crate::{
+    uart::{Parity, SerialConfig, StopBits},
+    WiringXError,
+};
+
+use super::{nmea::checksum, Gnss, GnssError};
+
+/// Sync characters starting every UBX frame.
+const UBX_SYNC: [u8; 2] = [0xb5, 0x62];
+/// Class of UBX configuration messages.
+const UBX_CLASS_CFG: u8 = 0x06;
+/// UBX message configuring a port.
+const UBX_CFG_PRT: u8 = 0x00;
+/// UBX message configuring the measurement rate.
+const UBX_CFG_RATE: u8 = 0x08;
+
+/// Command set understood by a receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandSet {
+    /// `$PMTK` sentences of MediaTek based receivers.
+    Mtk,
+    /// Binary UBX messages of u-blox receivers.
+    Ubx,
+}
+
+impl Gnss {
+    /// Sends an NMEA sentence, adding `$`, the checksum and the line ending to the body, for example `PMTK220,1000`.
+    pub fn send_nmea(&self, body: &str) -> Result<(), GnssError> {
+        let sentence = format!("${body}*{:02X}\r\n", checksum(body));
+
+        self.uart.write(sentence.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Sends a UBX message, adding the header and the checksum to the payload.
+    pub fn send_ubx(&self, class: u8, id: u8, payload: &[u8]) -> Result<(), GnssError> {
+        let len = u16::try_from(payload.len()).map_err(|_| GnssError::InvalidCommand)?;
+
+        let mut frame = UBX_SYNC.to_vec();
+        frame.extend_from_slice(&[class, id]);
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(payload);
+
+        // 8-bit Fletcher checksum over everything following the sync characters.
+        let (a, b) = frame[2..].iter().fold((0u8, 0u8), |(a, b), &byte| {
+            let a = a.wrapping_add(byte);
+            (a, b.wrapping_add(a))
+        });
+        frame.extend_from_slice(&[a, b]);
+
+        self.uart.write(&frame)?;
+
+        Ok(())
+    }
+
+    /// Sets the interval between navigation solutions, for example 100 ms for 10 Hz.
+    pub fn set_update_interval(
+        &self,
+        commands: CommandSet,
+        interval: Duration,
+    ) -> Result<(), GnssError> {
+        let millis = u16::try_from(interval.as_millis())
+            .ok()
+            .filter(|&millis| millis > 0)
+            .ok_or(GnssError::InvalidCommand)?;
+
+        match commands {
+            CommandSet::Mtk => self.send_nmea(&format!("PMTK220,{millis}")),
+            CommandSet::Ubx => {
+                let mut payload = millis.to_le_bytes().to_vec();
+                // One measurement per solution, aligned to GPS time.
+                payload.extend_from_slice(&1u16.to_le_bytes());
+                payload.extend_from_slice(&1u16.to_le_bytes());
+
+                self.send_ubx(UBX_CLASS_CFG, UBX_CFG_RATE, &payload)
+            }
+        }
+    }
+
+    /// Switches the receiver to another baud rate and reconfigures the UART to follow it.
+    ///
+    /// MTK receivers keep their framing. UBX receivers get configured for 8N1 on their first UART, with
+    /// NMEA and UBX enabled in both directions, and the UART follows to 8N1 as well.
+    pub fn set_baud_rate(&mut self, commands: CommandSet, baud_rate: u32) -> Result<(), GnssError> {
+        let current = self.uart.config()?;
+        let config = match commands {
+            CommandSet::Mtk => SerialConfig {
+                baud_rate,
+                ..current
+            },
+            CommandSet::Ubx => SerialConfig {
+                baud_rate,
+                data_bits: 8,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+                ..current
+            },
+        };
+        config.check().map_err(WiringXError::InvalidUARTConfig)?;
+
+        match commands {
+            CommandSet::Mtk => self.send_nmea(&format!("PMTK251,{baud_rate}"))?,
+            CommandSet::Ubx => {
+                let mut payload = vec![1, 0, 0, 0];
+                // 8 data bits, no parity and 1 stop bit.
+                payload.extend_from_slice(&0x08d0u32.to_le_bytes());
+                payload.extend_from_slice(&baud_rate.to_le_bytes());
+                payload.extend_from_slice(&0x0003u16.to_le_bytes());
+                payload.extend_from_slice(&0x0003u16.to_le_bytes());
+                payload.extend_from_slice(&[0; 4]);
+
+                self.send_ubx(UBX_CLASS_CFG, UBX_CFG_PRT, &payload)?
+            }
+        }
+
+        self.uart.drain()?;
+        self.uart.reconfigure(config)?;
+
+        // Whatever arrived during the switch is garbage.
+        self.buffer.clear();
+        self.stashed = None;
+        self.end_epoch();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::uart::{pty_pair, FlowControl};
+
+    const CONFIG_8N1: SerialConfig = SerialConfig {
+        baud_rate: 9600,
+        data_bits: 8,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+        flow_control: FlowControl::None,
+    };
+
+    #[test]
+    fn ubx_baud_rate_switches_to_8n1() {
+        // Pseudo terminals only support 8 data bits without parity, so the stop bits have to show the change.
+        let (receiver, uart) = pty_pair(SerialConfig {
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::Two,
+            flow_control: FlowControl::None,
+        });
+        let mut gnss = Gnss::new(uart);
+
+        gnss.set_baud_rate(CommandSet::Ubx, 115200).unwrap();
+
+        assert_eq!(
+            gnss.uart().config().unwrap(),
+            SerialConfig {
+                baud_rate: 115200,
+                data_bits: 8,
+                parity: Parity::None,
+                stop_bits: StopBits::One,
+                flow_control: FlowControl::None,
+            }
+        );
+
+        let mut frame = [0; 28];
+        let read = receiver.read(&mut frame, Duration::from_secs(1)).unwrap();
+        assert_eq!(read, 28);
+        assert_eq!(
+            frame,
+            [
+                0xb5, 0x62, 0x06, 0x00, 0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0xd0, 0x08, 0x00, 0x00,
+                0x00, 0xc2, 0x01, 0x00, 0x03, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0xbc, 0x5e,
+            ]
+        );
+    }
+
+    #[test]
+    fn mtk_baud_rate_keeps_framing() {
+        let config = SerialConfig {
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::Two,
+            flow_control: FlowControl::None,
+        };
+        let (receiver, uart) = pty_pair(config);
+        let mut gnss = Gnss::new(uart);
+
+        gnss.set_baud_rate(CommandSet::Mtk, 57600).unwrap();
+
+        assert_eq!(
+            gnss.uart().config().unwrap(),
+            SerialConfig {
+                baud_rate: 57600,
+                ..config
+            }
+        );
+
+        let mut sentence = [0; 64];
+        let read = receiver
+            .read(&mut sentence, Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(&sentence[..read], b"$PMTK251,57600*2C\r\n");
+    }
+
+    #[test]
+    fn ubx_update_interval() {
+        let (receiver, uart) = pty_pair(CONFIG_8N1);
+        let gnss = Gnss::new(uart);
+
+        gnss.set_update_interval(CommandSet::Ubx, Duration::from_secs(1))
+            .unwrap();
+
+        let mut frame = [0; 32];
+        let read = receiver.read(&mut frame, Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            frame[..read],
+            [0xb5, 0x62, 0x06, 0x08, 0x06, 0x00, 0xe8, 0x03, 0x01, 0x00, 0x01, 0x00, 0x01, 0x39]
+        );
+    }
+
+    #[test]
+    fn mtk_update_interval() {
+        let (receiver, uart) = pty_pair(CONFIG_8N1);
+        let gnss = Gnss::new(uart);
+
+        gnss.set_update_interval(CommandSet::Mtk, Duration::from_millis(200))
+            .unwrap();
+
+        let mut sentence = [0; 64];
+        let read = receiver
+            .read(&mut sentence, Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(&sentence[..read], b"$PMTK220,200*2C\r\n");
+    }
+
+    #[test]
+    fn update_interval_out_of_range() {
+        let (receiver, uart) = pty_pair(CONFIG_8N1);
+        let gnss = Gnss::new(uart);
+
+        for commands in [CommandSet::Mtk, CommandSet::Ubx] {
+            for interval in [
+                Duration::ZERO,
+                Duration::from_micros(999),
+                Duration::from_millis(65536),
+            ] {
+                assert!(matches!(
+                    gnss.set_update_interval(commands, interval),
+                    Err(GnssError::InvalidCommand)
+                ));
+            }
+        }
+
+        let mut sent = [0; 64];
+        assert_eq!(receiver.read(&mut sent, Duration::ZERO).unwrap(), 0);
+    }
+}
@@ -0,0 +1,630 @@
+//! Parsing of NMEA 0183 sentences.
+
+use super::GnssError;
+
+/// Satellite system sending a sentence, given by its talker ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Talker {
+    /// GPS, `GP`.
+    Gps,
+    /// GLONASS, `GL`.
+    Glonass,
+    /// Galileo, `GA`.
+    Galileo,
+    /// BeiDou, `GB` or `BD`.
+    Beidou,
+    /// QZSS, `GQ` or `QZ`.
+    Qzss,
+    /// NavIC, `GI`.
+    Navic,
+    /// Solutions combining several systems, `GN`.
+    Combined,
+    /// Any other talker ID.
+    Other([u8; 2]),
+}
+
+impl Talker {
+    fn from_id(id: [u8; 2]) -> Self {
+        match &id {
+            b"GP" => Self::Gps,
+            b"GL" => Self::Glonass,
+            b"GA" => Self::Galileo,
+            b"GB" | b"BD" => Self::Beidou,
+            b"GQ" | b"QZ" => Self::Qzss,
+            b"GI" => Self::Navic,
+            b"GN" => Self::Combined,
+            _ => Self::Other(id),
+        }
+    }
+}
+
+/// Time of day in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UtcTime {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millisecond: u16,
+}
+
+/// Date in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+/// Position in degrees, north and east being positive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Quality of a fix reported by GGA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FixQuality {
+    /// No fix.
+    #[default]
+    Invalid,
+    /// Autonomous fix.
+    Gps,
+    /// Differential fix.
+    Dgps,
+    /// Fix of a precise positioning service.
+    Pps,
+    /// RTK fix with fixed integers.
+    Rtk,
+    /// RTK fix with floating integers.
+    FloatRtk,
+    /// Estimated by dead reckoning.
+    Estimated,
+    /// Entered manually.
+    Manual,
+    /// Simulated.
+    Simulation,
+    /// Any other quality indicator.
+    Other(u8),
+}
+
+impl FixQuality {
+    fn from_indicator(indicator: u8) -> Self {
+        match indicator {
+            0 => Self::Invalid,
+            1 => Self::Gps,
+            2 => Self::Dgps,
+            3 => Self::Pps,
+            4 => Self::Rtk,
+            5 => Self::FloatRtk,
+            6 => Self::Estimated,
+            7 => Self::Manual,
+            8 => Self::Simulation,
+            indicator => Self::Other(indicator),
+        }
+    }
+}
+
+/// Dimension of a fix reported by GSA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FixMode {
+    /// No fix.
+    #[default]
+    NoFix,
+    /// Fix without altitude.
+    Fix2d,
+    /// Fix with altitude.
+    Fix3d,
+}
+
+/// A satellite in view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Satellite {
+    /// System the satellite belongs to.
+    pub system: Talker,
+    /// Satellite ID, numbered as by the receiver.
+    pub prn: u8,
+    /// Elevation in degrees.
+    pub elevation: Option<u8>,
+    /// Azimuth in degrees from true north.
+    pub azimuth: Option<u16>,
+    /// Signal to noise ratio in dB-Hz, `None` when not tracked.
+    pub snr: Option<u8>,
+}
+
+/// Fix data, sentence `GGA`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gga {
+    pub time: Option<UtcTime>,
+    pub position: Option<Position>,
+    pub quality: FixQuality,
+    /// Number of satellites used for the fix.
+    pub satellites: Option<u8>,
+    /// Horizontal dilution of precision.
+    pub hdop: Option<f32>,
+    /// Altitude above mean sea level in meters.
+    pub altitude: Option<f32>,
+    /// Height of the geoid above the WGS84 ellipsoid in meters.
+    pub geoid_separation: Option<f32>,
+}
+
+/// Recommended minimum data, sentence `RMC`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmc {
+    pub time: Option<UtcTime>,
+    /// Whether the receiver considers the data valid.
+    pub valid: bool,
+    pub position: Option<Position>,
+    /// Speed over ground in knots.
+    pub speed_knots: Option<f32>,
+    /// Course over ground in degrees from true north.
+    pub course: Option<f32>,
+    pub date: Option<Date>,
+    /// Magnetic variation in degrees, east being positive.
+    pub magnetic_variation: Option<f32>,
+}
+
+/// Active satellites and dilution of precision, sentence `GSA`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsa {
+    /// Whether the receiver switches between 2D and 3D fixes by itself.
+    pub automatic: bool,
+    pub mode: FixMode,
+    /// IDs of the satellites used for the fix.
+    pub satellites: Vec<u8>,
+    pub pdop: Option<f32>,
+    pub hdop: Option<f32>,
+    pub vdop: Option<f32>,
+}
+
+/// Satellites in view, sentence `GSV`.
+///
+/// The satellites of one system get spread over several sentences.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsv {
+    /// Number of sentences listing the satellites of this system.
+    pub total: u8,
+    /// Number of this sentence, starting at 1.
+    pub number: u8,
+    /// Number of satellites in view of this system.
+    pub in_view: u8,
+    pub satellites: Vec<Satellite>,
+}
+
+/// Course and speed over ground, sentence `VTG`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vtg {
+    /// Course in degrees from true north.
+    pub course: Option<f32>,
+    /// Course in degrees from magnetic north.
+    pub course_magnetic: Option<f32>,
+    pub speed_knots: Option<f32>,
+    pub speed_kmh: Option<f32>,
+}
+
+/// A parsed NMEA sentence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sentence {
+    Gga(Gga),
+    Rmc(Rmc),
+    Gsa(Gsa),
+    Gsv(Gsv),
+    Vtg(Vtg),
+}
+
+/// Calculates the checksum of the sentence between `$` and `*`.
+pub fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+/// Parses a sentence like `$GPGGA,...*hh`, with or without the line ending.
+///
+/// Sentences without a valid checksum get rejected, unsupported ones return `GnssError::Unsupported`.
+pub fn parse(line: &str) -> Result<(Talker, Sentence), GnssError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+
+    let body = line.strip_prefix('$').ok_or(GnssError::InvalidSentence)?;
+    let (body, expected) = body.split_once('*').ok_or(GnssError::InvalidSentence)?;
+
+    let expected = u8::from_str_radix(expected, 16).map_err(|_| GnssError::InvalidSentence)?;
+    if checksum(body) != expected {
+        return Err(GnssError::Checksum);
+    }
+
+    let mut fields = body.split(',');
+    let address = fields.next().unwrap_or_default().as_bytes();
+
+    // Proprietary sentences start with `P` and have no talker ID.
+    if address.len() != 5 || address[0] == b'P' {
+        return Err(GnssError::Unsupported);
+    }
+
+    let talker = Talker::from_id([address[0], address[1]]);
+    let fields: Vec<&str> = fields.collect();
+
+    let sentence = match &address[2..] {
+        b"GGA" => Sentence::Gga(gga(&fields)?),
+        b"RMC" => Sentence::Rmc(rmc(&fields)?),
+        b"GSA" => Sentence::Gsa(gsa(&fields)?),
+        b"GSV" => Sentence::Gsv(gsv(talker, &fields)?),
+        b"VTG" => Sentence::Vtg(vtg(&fields)?),
+        _ => return Err(GnssError::Unsupported),
+    };
+
+    Ok((talker, sentence))
+}
+
+fn gga(fields: &[&str]) -> Result<Gga, GnssError> {
+    Ok(Gga {
+        time: time(field(fields, 0)?)?,
+        position: position(fields.get(1..).unwrap_or_default())?,
+        quality: FixQuality::from_indicator(number(field(fields, 5)?)?.unwrap_or(0)),
+        satellites: number(field(fields, 6)?)?,
+        hdop: number(field(fields, 7)?)?,
+        altitude: number(field(fields, 8)?)?,
+        geoid_separation: number(fields.get(10).copied().unwrap_or_default())?,
+    })
+}
+
+fn rmc(fields: &[&str]) -> Result<Rmc, GnssError> {
+    let variation: Option<f32> = number(fields.get(9).copied().unwrap_or_default())?;
+    let variation = match fields.get(10).copied() {
+        Some("W") => variation.map(|variation| -variation),
+        _ => variation,
+    };
+
+    Ok(Rmc {
+        time: time(field(fields, 0)?)?,
+        valid: field(fields, 1)? == "A",
+        position: position(fields.get(2..).unwrap_or_default())?,
+        speed_knots: number(field(fields, 6)?)?,
+        course: number(field(fields, 7)?)?,
+        date: date(field(fields, 8)?)?,
+        magnetic_variation: variation,
+    })
+}
+
+fn gsa(fields: &[&str]) -> Result<Gsa, GnssError> {
+    let mode = match field(fields, 1)? {
+        "2" => FixMode::Fix2d,
+        "3" => FixMode::Fix3d,
+        _ => FixMode::NoFix,
+    };
+
+    let satellites = (2..14)
+        .map(|index| number(field(fields, index)?))
+        .filter_map(Result::transpose)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Gsa {
+        automatic: field(fields, 0)? == "A",
+        mode,
+        satellites,
+        pdop: number(field(fields, 14)?)?,
+        hdop: number(field(fields, 15)?)?,
+        vdop: number(field(fields, 16)?)?,
+    })
+}
+
+fn gsv(talker: Talker, fields: &[&str]) -> Result<Gsv, GnssError> {
+    let required = |index| number(field(fields, index)?)?.ok_or(GnssError::InvalidSentence);
+
+    // Four fields per satellite, possibly followed by a signal ID.
+    let satellites = fields
+        .get(3..)
+        .unwrap_or_default()
+        .chunks(4)
+        .filter(|chunk| chunk.len() == 4 && !chunk[0].is_empty())
+        .map(|chunk| {
+            Ok(Satellite {
+                system: talker,
+                prn: number(chunk[0])?.ok_or(GnssError::InvalidSentence)?,
+                elevation: number(chunk[1])?,
+                azimuth: number(chunk[2])?,
+                snr: number(chunk[3])?,
+            })
+        })
+        .collect::<Result<_, GnssError>>()?;
+
+    Ok(Gsv {
+        total: required(0)?,
+        number: required(1)?,
+        in_view: required(2)?,
+        satellites,
+    })
+}
+
+fn vtg(fields: &[&str]) -> Result<Vtg, GnssError> {
+    Ok(Vtg {
+        course: number(field(fields, 0)?)?,
+        course_magnetic: number(field(fields, 2)?)?,
+        speed_knots: number(field(fields, 4)?)?,
+        speed_kmh: number(field(fields, 6)?)?,
+    })
+}
+
+fn field<'a>(fields: &[&'a str], index: usize) -> Result<&'a str, GnssError> {
+    fields.get(index).copied().ok_or(GnssError::InvalidSentence)
+}
+
+/// Parses a number, empty fields being `None`.
+fn number<T: std::str::FromStr>(field: &str) -> Result<Option<T>, GnssError> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+
+    field
+        .parse()
+        .map(Some)
+        .map_err(|_| GnssError::InvalidSentence)
+}
+
+/// Parses `hhmmss.sss`.
+fn time(field: &str) -> Result<Option<UtcTime>, GnssError> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+
+    let (Some(hour), Some(minute), Some(second)) =
+        (field.get(0..2), field.get(2..4), field.get(4..))
+    else {
+        return Err(GnssError::InvalidSentence);
+    };
+
+    let second: f64 = second.parse().map_err(|_| GnssError::InvalidSentence)?;
+
+    Ok(Some(UtcTime {
+        hour: hour.parse().map_err(|_| GnssError::InvalidSentence)?,
+        minute: minute.parse().map_err(|_| GnssError::InvalidSentence)?,
+        second: second as u8,
+        millisecond: (second.fract() * 1000.0).round() as u16,
+    }))
+}
+
+/// Parses `ddmmyy`.
+fn date(field: &str) -> Result<Option<Date>, GnssError> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+
+    let parts: Option<Vec<u8>> = (0..3)
+        .map(|index| field.get(index * 2..index * 2 + 2)?.parse().ok())
+        .collect();
+
+    match parts.as_deref() {
+        Some(&[day, month, year]) if field.len() == 6 => Ok(Some(Date {
+            // Two digit years before 80 belong to this century.
+            year: if year < 80 { 2000 } else { 1900 } + year as u16,
+            month,
+            day,
+        })),
+        _ => Err(GnssError::InvalidSentence),
+    }
+}
+
+/// Parses latitude, hemisphere, longitude and hemisphere in `ddmm.mmmm,N,dddmm.mmmm,E` form.
+fn position(fields: &[&str]) -> Result<Option<Position>, GnssError> {
+    let (Some(latitude), Some(longitude)) = (
+        coordinate(field(fields, 0)?, field(fields, 1)?, 2)?,
+        coordinate(field(fields, 2)?, field(fields, 3)?, 3)?,
+    ) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Position {
+        latitude,
+        longitude,
+    }))
+}
+
+fn coordinate(
+    value: &str,
+    hemisphere: &str,
+    degree_digits: usize,
+) -> Result<Option<f64>, GnssError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    let (Some(degrees), Some(minutes)) = (value.get(..degree_digits), value.get(degree_digits..))
+    else {
+        return Err(GnssError::InvalidSentence);
+    };
+
+    let degrees: f64 = degrees.parse().map_err(|_| GnssError::InvalidSentence)?;
+    let minutes: f64 = minutes.parse().map_err(|_| GnssError::InvalidSentence)?;
+    let coordinate = degrees + minutes / 60.0;
+
+    match hemisphere {
+        "N" | "E" => Ok(Some(coordinate)),
+        "S" | "W" => Ok(Some(-coordinate)),
+        _ => Err(GnssError::InvalidSentence),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u8, minute: u8, second: u8, millisecond: u16) -> Option<UtcTime> {
+        Some(UtcTime {
+            hour,
+            minute,
+            second,
+            millisecond,
+        })
+    }
+
+    fn position(latitude: f64, longitude: f64) -> Option<Position> {
+        Some(Position {
+            latitude,
+            longitude,
+        })
+    }
+
+    #[test]
+    fn rejects_broken_sentences() {
+        let cases = [
+            // Altitude changed from 545.4 without updating the checksum.
+            (
+                "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.5,M,46.9,M,,*47",
+                GnssError::Checksum,
+            ),
+            (
+                "$GPVTG,054.7,T,034.4,M,005.5,N,010.2,K*49",
+                GnssError::Checksum,
+            ),
+            (
+                "GPVTG,054.7,T,034.4,M,005.5,N,010.2,K*48",
+                GnssError::InvalidSentence,
+            ),
+            (
+                "$GPVTG,054.7,T,034.4,M,005.5,N,010.2,K",
+                GnssError::InvalidSentence,
+            ),
+            (
+                "$GPVTG,054.7,T,034.4,M,005.5,N,010.2,K*XY",
+                GnssError::InvalidSentence,
+            ),
+            ("$PMTK001,220,3*30", GnssError::Unsupported),
+            (
+                "$GPZDA,201530.00,04,07,2002,00,00*60",
+                GnssError::Unsupported,
+            ),
+        ];
+
+        for (line, expected) in cases {
+            let error = parse(line).unwrap_err();
+            assert_eq!(
+                std::mem::discriminant(&error),
+                std::mem::discriminant(&expected),
+                "{line}: {error:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_sentences() {
+        let cases = [
+            (
+                "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n",
+                Talker::Gps,
+                Sentence::Gga(Gga {
+                    time: time(12, 35, 19, 0),
+                    position: position(48.0 + 7.038 / 60.0, 11.0 + 31.0 / 60.0),
+                    quality: FixQuality::Gps,
+                    satellites: Some(8),
+                    hdop: Some(0.9),
+                    altitude: Some(545.4),
+                    geoid_separation: Some(46.9),
+                }),
+            ),
+            (
+                "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A",
+                Talker::Gps,
+                Sentence::Rmc(Rmc {
+                    time: time(12, 35, 19, 0),
+                    valid: true,
+                    position: position(48.0 + 7.038 / 60.0, 11.0 + 31.0 / 60.0),
+                    speed_knots: Some(22.4),
+                    course: Some(84.4),
+                    date: Some(Date {
+                        year: 1994,
+                        month: 3,
+                        day: 23,
+                    }),
+                    magnetic_variation: Some(-3.1),
+                }),
+            ),
+            (
+                "$GPRMC,000000.00,V,,,,,,,010100,,,N*7D",
+                Talker::Gps,
+                Sentence::Rmc(Rmc {
+                    time: time(0, 0, 0, 0),
+                    valid: false,
+                    position: None,
+                    speed_knots: None,
+                    course: None,
+                    date: Some(Date {
+                        year: 2000,
+                        month: 1,
+                        day: 1,
+                    }),
+                    magnetic_variation: None,
+                }),
+            ),
+            (
+                "$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39",
+                Talker::Gps,
+                Sentence::Gsa(Gsa {
+                    automatic: true,
+                    mode: FixMode::Fix3d,
+                    satellites: vec![4, 5, 9, 12, 24],
+                    pdop: Some(2.5),
+                    hdop: Some(1.3),
+                    vdop: Some(2.1),
+                }),
+            ),
+            (
+                "$GPGSV,2,2,08,15,10,050,,18,65,280,37,22,33,120,40,25,05,015,*74",
+                Talker::Gps,
+                Sentence::Gsv(Gsv {
+                    total: 2,
+                    number: 2,
+                    in_view: 8,
+                    satellites: [(15, 10, 50, None), (18, 65, 280, Some(37))]
+                        .into_iter()
+                        .chain([(22, 33, 120, Some(40)), (25, 5, 15, None)])
+                        .map(|(prn, elevation, azimuth, snr)| Satellite {
+                            system: Talker::Gps,
+                            prn,
+                            elevation: Some(elevation),
+                            azimuth: Some(azimuth),
+                            snr,
+                        })
+                        .collect(),
+                }),
+            ),
+            (
+                "$GPVTG,054.7,T,034.4,M,005.5,N,010.2,K*48",
+                Talker::Gps,
+                Sentence::Vtg(Vtg {
+                    course: Some(54.7),
+                    course_magnetic: Some(34.4),
+                    speed_knots: Some(5.5),
+                    speed_kmh: Some(10.2),
+                }),
+            ),
+        ];
+
+        for (line, talker, sentence) in cases {
+            assert_eq!(parse(line).unwrap(), (talker, sentence), "{line}");
+        }
+    }
+
+    #[test]
+    fn hemisphere_signs() {
+        let cases = [
+            (
+                "$GNGGA,235959.50,3351.4210,S,15112.7290,E,2,12,0.8,42.0,M,22.1,M,1.0,0000*72",
+                -(33.0 + 51.421 / 60.0),
+                151.0 + 12.729 / 60.0,
+            ),
+            (
+                "$GNGGA,000000.50,0000.0000,N,07400.6000,W,1,05,1.5,10.0,M,-34.2,M,,*78",
+                0.0,
+                -(74.0 + 0.6 / 60.0),
+            ),
+        ];
+
+        for (line, latitude, longitude) in cases {
+            let Ok((Talker::Combined, Sentence::Gga(gga))) = parse(line) else {
+                panic!("{line} is no combined GGA sentence");
+            };
+            let position = gga.position.unwrap();
+
+            assert!((position.latitude - latitude).abs() < 1e-9, "{line}");
+            assert!((position.longitude - longitude).abs() < 1e-9, "{line}");
+        }
+    }
+}
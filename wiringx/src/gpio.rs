@@ -1,5 +1,15 @@
 //! General purpose input output related objects.
 
+mod button;
+mod edge;
+mod encoder;
+mod pulse;
+
+pub use button::{Button, ButtonConfig, ButtonEvent};
+pub use edge::{Edge, EdgeEvent, FrequencyMeter, PulseCounter, PulseMeasurement};
+pub use encoder::{EncoderConfig, EncoderEvent, EncoderMode, QuadratureEncoder};
+pub use pulse::PulseWidth;
+
 use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use parking_lot::Mutex;
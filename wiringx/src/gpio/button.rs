@@ -0,0 +1,251 @@
+//! Debounced push buttons emitting gesture events.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::WiringXError;
+
+use super::{Input, IsrMode, Pin, Value};
+
+/// Longest time the worker thread blocks on an interrupt before checking if the button got dropped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Configuration of a debounced button.
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonConfig {
+    /// Time the pin has to keep its level after an edge before the change is accepted.
+    pub debounce: Duration,
+    /// Level the pin has while the button is pressed.
+    pub active_level: Value,
+    /// Duration the button has to be held down to emit a `LongPress` event. `None` disables long presses.
+    pub long_press: Option<Duration>,
+    /// Maximum time between releasing and pressing the button again to emit a `DoubleClick` event.
+    /// `None` disables double clicks.
+    pub double_click: Option<Duration>,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(20),
+            active_level: Value::Low,
+            long_press: Some(Duration::from_millis(1000)),
+            double_click: Some(Duration::from_millis(300)),
+        }
+    }
+}
+
+/// High level events emitted by a `Button`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    /// The button got pressed.
+    Press,
+    /// The button got released.
+    Release,
+    /// The button is held down for longer than the configured long press duration.
+    LongPress,
+    /// The button got pressed a second time within the configured double click window.
+    ///
+    /// Gets emitted right after the `Press` event of the second click.
+    DoubleClick,
+}
+
+/// A debounced button on an input pin.
+///
+/// Listens to both edges of the pin in a background thread and converts the bouncing signal into `ButtonEvent`s.
+#[derive(Debug)]
+pub struct Button {
+    number: i32,
+    events: Receiver<ButtonEvent>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Button {
+    /// Creates a button from the given input pin and starts listening for events.
+    ///
+    /// Overrides the interrupt service routine mode of the pin to `IsrMode::Both`.
+    pub fn new(pin: Pin<Input>, config: ButtonConfig) -> Result<Self, WiringXError> {
+        pin.set_isr_mode(IsrMode::Both)?;
+
+        let number = pin.number();
+        let (sender, events) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+
+        let worker = {
+            let running = running.clone();
+            thread::Builder::new()
+                .name(format!("wiringx-button-{number}"))
+                .spawn(move || Debouncer::new(pin, config, sender).run(&running))
+                .map_err(WiringXError::Io)?
+        };
+
+        Ok(Self {
+            number,
+            events,
+            running,
+            worker: Some(worker),
+        })
+    }
+
+    /// Returns the number of the underlying pin.
+    pub fn number(&self) -> i32 {
+        self.number
+    }
+
+    /// Blocks until the next event arrives.
+    pub fn recv(&self) -> Option<ButtonEvent> {
+        self.events.recv().ok()
+    }
+
+    /// Blocks until the next event arrives or the timeout elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ButtonEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next event if one is pending.
+    pub fn try_recv(&self) -> Option<ButtonEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Returns an iterator blocking on the next event.
+    pub fn events(&self) -> impl Iterator<Item = ButtonEvent> + '_ {
+        self.events.iter()
+    }
+}
+
+impl Drop for Button {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// State machine running in the worker thread of a `Button`.
+struct Debouncer {
+    pin: Pin<Input>,
+    config: ButtonConfig,
+    sender: Sender<ButtonEvent>,
+    pressed: bool,
+    pressed_at: Option<Instant>,
+    long_press_sent: bool,
+    last_click: Option<Instant>,
+    double_clicked: bool,
+}
+
+impl Debouncer {
+    fn new(pin: Pin<Input>, config: ButtonConfig, sender: Sender<ButtonEvent>) -> Self {
+        let pressed = pin.read() == config.active_level;
+
+        Self {
+            pin,
+            config,
+            sender,
+            pressed,
+            pressed_at: pressed.then(Instant::now),
+            long_press_sent: pressed,
+            last_click: None,
+            double_clicked: false,
+        }
+    }
+
+    fn run(mut self, running: &AtomicBool) {
+        while running.load(Ordering::Relaxed) {
+            let timeout = self.next_timeout();
+
+            if self.pin.wait_for_interrupt(timeout).is_ok() {
+                let Some(pressed) = self.settle(running) else {
+                    return;
+                };
+
+                if pressed != self.pressed && !self.transition(pressed) {
+                    return;
+                }
+            }
+
+            if !self.check_long_press() {
+                return;
+            }
+        }
+    }
+
+    /// Waits until the pin kept its level for a whole debounce window and returns whether the button is pressed.
+    ///
+    /// Every edge restarts the window, so bounces anywhere within it are ignored. Returns `None` if the button got
+    /// dropped meanwhile.
+    fn settle(&self, running: &AtomicBool) -> Option<bool> {
+        while self.pin.wait_for_interrupt(self.config.debounce).is_ok() {
+            if !running.load(Ordering::Relaxed) {
+                return None;
+            }
+        }
+
+        Some(self.pin.read() == self.config.active_level)
+    }
+
+    /// Time to wait for the next interrupt, shortened if a long press is due earlier.
+    fn next_timeout(&self) -> Duration {
+        match (self.pressed_at, self.config.long_press) {
+            (Some(pressed_at), Some(long_press)) if !self.long_press_sent => long_press
+                .saturating_sub(pressed_at.elapsed())
+                .clamp(Duration::from_millis(1), POLL_INTERVAL),
+            _ => POLL_INTERVAL,
+        }
+    }
+
+    /// Applies a debounced level change. Returns false if the receiving side is gone.
+    fn transition(&mut self, pressed: bool) -> bool {
+        let now = Instant::now();
+        self.pressed = pressed;
+
+        if pressed {
+            self.pressed_at = Some(now);
+            self.long_press_sent = false;
+
+            if !self.emit(ButtonEvent::Press) {
+                return false;
+            }
+
+            self.double_clicked = match (self.last_click.take(), self.config.double_click) {
+                (Some(last_click), Some(window)) => now.duration_since(last_click) <= window,
+                _ => false,
+            };
+
+            !self.double_clicked || self.emit(ButtonEvent::DoubleClick)
+        } else {
+            self.pressed_at = None;
+
+            // Long presses and the second click of a double click do not open a new double click window.
+            self.last_click = (!self.long_press_sent && !self.double_clicked).then_some(now);
+
+            self.emit(ButtonEvent::Release)
+        }
+    }
+
+    /// Emits a long press once the button was held long enough. Returns false if the receiving side is gone.
+    fn check_long_press(&mut self) -> bool {
+        let (Some(pressed_at), Some(long_press)) = (self.pressed_at, self.config.long_press) else {
+            return true;
+        };
+
+        if self.long_press_sent || pressed_at.elapsed() < long_press {
+            return true;
+        }
+
+        self.long_press_sent = true;
+        self.emit(ButtonEvent::LongPress)
+    }
+
+    fn emit(&self, event: ButtonEvent) -> bool {
+        self.sender.send(event).is_ok()
+    }
+}
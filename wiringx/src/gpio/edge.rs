@@ -0,0 +1,348 @@
+//! Timestamped edge events, pulse counting and frequency measurement.
+
+use std::{
+    io,
+    os::fd::RawFd,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use wiringx_sys::wiringXSelectableFd;
+
+use crate::WiringXError;
+
+use super::{Input, IsrMode, Pin, Value};
+
+/// Longest time a worker thread blocks on an edge before checking if its owner got dropped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Direction of a signal change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edge {
+    /// The signal changed from low to high.
+    Rising,
+    /// The signal changed from high to low.
+    Falling,
+}
+
+/// A detected edge on an input pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeEvent {
+    /// Direction of the edge, derived from the level read after the interrupt.
+    pub edge: Edge,
+    /// Level of the pin read right after the interrupt.
+    pub level: Value,
+    /// Monotonic time the interrupt got delivered to this process.
+    pub timestamp: Instant,
+}
+
+impl Pin<Input> {
+    /// Returns the raw file descriptor wiringX uses to detect interrupts on this pin.
+    ///
+    /// Only valid after the interrupt service routine mode got set using `set_isr_mode`.
+    pub fn selectable_fd(&self) -> Result<RawFd, WiringXError> {
+        let fd = unsafe { wiringXSelectableFd(self.number) };
+
+        if fd < 0 {
+            Err(WiringXError::Io(io::Error::last_os_error()))
+        } else {
+            Ok(fd)
+        }
+    }
+
+    /// Suspends the thread until an edge was detected on this pin or the function times out.
+    ///
+    /// Unlike `wait_for_interrupt` this reports which edge happened and when.
+    /// Returns `Ok(None)` on timeout.
+    pub fn wait_for_edge(&self, timeout: Duration) -> Result<Option<EdgeEvent>, WiringXError> {
+        let fd = self.selectable_fd()?;
+
+        wait_for_edge(fd, timeout).map_err(WiringXError::Io)
+    }
+}
+
+/// Polls the sysfs value file descriptor of a pin for the next edge.
+pub(crate) fn wait_for_edge(fd: RawFd, timeout: Duration) -> io::Result<Option<EdgeEvent>> {
+    let Some(timestamp) = poll_edges(&[fd], timeout)? else {
+        return Ok(None);
+    };
+
+    let level = read_level(fd)?;
+    let edge = match level {
+        Value::High => Edge::Rising,
+        Value::Low => Edge::Falling,
+    };
+
+    Ok(Some(EdgeEvent {
+        edge,
+        level,
+        timestamp,
+    }))
+}
+
+/// Waits until an edge occurs on any of the given sysfs value file descriptors.
+///
+/// Returns the time the wait ended or `None` on timeout or if a signal interrupted the wait.
+pub(crate) fn poll_edges(fds: &[RawFd], timeout: Duration) -> io::Result<Option<Instant>> {
+    let mut poll_fds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|&fd| libc::pollfd {
+            fd,
+            events: libc::POLLPRI | libc::POLLERR,
+            revents: 0,
+        })
+        .collect();
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+
+    let result = unsafe {
+        libc::ppoll(
+            poll_fds.as_mut_ptr(),
+            poll_fds.len() as libc::nfds_t,
+            &timeout,
+            std::ptr::null(),
+        )
+    };
+    let timestamp = Instant::now();
+
+    if result < 0 {
+        let error = io::Error::last_os_error();
+        return match error.kind() {
+            io::ErrorKind::Interrupted => Ok(None),
+            _ => Err(error),
+        };
+    }
+
+    Ok((result > 0).then_some(timestamp))
+}
+
+/// Reads the level from the sysfs value file descriptor of a pin.
+///
+/// Reading the value from the start also acknowledges a pending interrupt.
+pub(crate) fn read_level(fd: RawFd) -> io::Result<Value> {
+    let mut value = 0u8;
+    let read = unsafe { libc::pread(fd, &mut value as *mut u8 as *mut libc::c_void, 1, 0) };
+
+    if read != 1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if value == b'1' {
+        Ok(Value::High)
+    } else {
+        Ok(Value::Low)
+    }
+}
+
+/// Counts edges on an input pin in a background thread.
+///
+/// Useful for flow meters, anemometers and other sensors reporting a quantity as a number of pulses.
+#[derive(Debug)]
+pub struct PulseCounter {
+    number: i32,
+    count: Arc<AtomicU64>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl PulseCounter {
+    /// Starts counting the edges of the given pin.
+    ///
+    /// `mode` selects which edges get counted and must not be `IsrMode::None` or `IsrMode::Unknown`.
+    pub fn new(pin: Pin<Input>, mode: IsrMode) -> Result<Self, WiringXError> {
+        if matches!(mode, IsrMode::None | IsrMode::Unknown) {
+            return Err(WiringXError::Other(
+                "Pulses can only be counted on rising, falling or both edges.".to_string(),
+            ));
+        }
+
+        pin.set_isr_mode(mode)?;
+        let fd = pin.selectable_fd()?;
+
+        let number = pin.number();
+        let count = Arc::new(AtomicU64::new(0));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let worker = {
+            let count = count.clone();
+            let running = running.clone();
+            thread::Builder::new()
+                .name(format!("wiringx-counter-{number}"))
+                .spawn(move || {
+                    // Keeps the pin reserved for as long as the counter runs.
+                    let _pin = pin;
+
+                    while running.load(Ordering::Relaxed) {
+                        match wait_for_edge(fd, POLL_INTERVAL) {
+                            Ok(Some(_)) => {
+                                count.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Ok(None) => (),
+                            Err(_) => break,
+                        }
+                    }
+                })
+                .map_err(WiringXError::Io)?
+        };
+
+        Ok(Self {
+            number,
+            count,
+            running,
+            worker: Some(worker),
+        })
+    }
+
+    /// Returns the number of the underlying pin.
+    pub fn number(&self) -> i32 {
+        self.number
+    }
+
+    /// Returns the number of edges counted since creation or the last reset.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Resets the counter to zero, returning the count before the reset.
+    pub fn reset(&self) -> u64 {
+        self.count.swap(0, Ordering::Relaxed)
+    }
+
+    /// Blocks for the given window and returns the number of edges counted during it.
+    pub fn count_over(&self, window: Duration) -> u64 {
+        let start = self.count();
+        thread::sleep(window);
+
+        self.count().wrapping_sub(start)
+    }
+
+    /// Blocks for the given window and returns the counted edges per second.
+    pub fn rate_over(&self, window: Duration) -> f64 {
+        let start = Instant::now();
+        let count = self.count_over(window);
+
+        count as f64 / start.elapsed().as_secs_f64()
+    }
+}
+
+impl Drop for PulseCounter {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Result of a `FrequencyMeter` measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PulseMeasurement {
+    /// Number of rising edges seen during the measurement window.
+    pub pulses: usize,
+    /// Average time between two rising edges.
+    pub period: Duration,
+    /// Average time the signal stayed high after a rising edge.
+    pub pulse_width: Duration,
+    /// Signal frequency in Hertz.
+    pub frequency: f64,
+    /// Proportion of the period the signal is high, between 0 and 1.
+    pub duty_cycle: f64,
+}
+
+/// Measures frequency, period and pulse width of a signal on an input pin.
+#[derive(Debug)]
+pub struct FrequencyMeter {
+    pin: Pin<Input>,
+    fd: RawFd,
+}
+
+impl FrequencyMeter {
+    /// Creates a frequency meter on the given pin.
+    ///
+    /// Overrides the interrupt service routine mode of the pin to `IsrMode::Both`.
+    pub fn new(pin: Pin<Input>) -> Result<Self, WiringXError> {
+        pin.set_isr_mode(IsrMode::Both)?;
+        let fd = pin.selectable_fd()?;
+
+        Ok(Self { pin, fd })
+    }
+
+    /// Returns the number of the underlying pin.
+    pub fn number(&self) -> i32 {
+        self.pin.number()
+    }
+
+    /// Collects all edges for the given window.
+    pub fn capture(&self, window: Duration) -> Result<Vec<EdgeEvent>, WiringXError> {
+        let end = Instant::now() + window;
+        let mut events = Vec::new();
+
+        loop {
+            let remaining = end.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(events);
+            }
+
+            if let Some(event) = wait_for_edge(self.fd, remaining).map_err(WiringXError::Io)? {
+                events.push(event);
+            }
+        }
+    }
+
+    /// Measures the signal for the given window.
+    ///
+    /// Returns `Ok(None)` if less than two rising edges occurred during the window.
+    pub fn measure(&self, window: Duration) -> Result<Option<PulseMeasurement>, WiringXError> {
+        let events = self.capture(window)?;
+
+        Ok(analyze(&events))
+    }
+}
+
+/// Computes averaged period and pulse width from a sequence of edges.
+fn analyze(events: &[EdgeEvent]) -> Option<PulseMeasurement> {
+    let rising: Vec<Instant> = events
+        .iter()
+        .filter(|event| event.edge == Edge::Rising)
+        .map(|event| event.timestamp)
+        .collect();
+
+    let (first, last) = (*rising.first()?, *rising.last()?);
+    if rising.len() < 2 || first == last {
+        return None;
+    }
+
+    let period = (last - first) / (rising.len() - 1) as u32;
+
+    let mut high_time = Duration::ZERO;
+    let mut high_count = 0u32;
+    for pair in events.windows(2) {
+        if pair[0].edge == Edge::Rising && pair[1].edge == Edge::Falling {
+            high_time += pair[1].timestamp - pair[0].timestamp;
+            high_count += 1;
+        }
+    }
+
+    let pulse_width = if high_count == 0 {
+        Duration::ZERO
+    } else {
+        high_time / high_count
+    };
+
+    let frequency = 1.0 / period.as_secs_f64();
+    let duty_cycle = (pulse_width.as_secs_f64() / period.as_secs_f64()).clamp(0.0, 1.0);
+
+    Some(PulseMeasurement {
+        pulses: rising.len(),
+        period,
+        pulse_width,
+        frequency,
+        duty_cycle,
+    })
+}
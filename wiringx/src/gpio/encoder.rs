@@ -0,0 +1,283 @@
+//! Quadrature rotary encoder decoding.
+
+use std::{
+    collections::VecDeque,
+    os::fd::RawFd,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::WiringXError;
+
+use super::{
+    edge::{poll_edges, read_level},
+    Input, IsrMode, Pin, Value,
+};
+
+/// Longest time the worker thread blocks on an edge before checking if the encoder got dropped.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Marks a transition in which both channels changed at once, which means at least one step got missed.
+const INVALID: i8 = 2;
+
+/// Quarter steps for every transition from the previous state (row) to the next state (column).
+///
+/// A state is encoded as `A << 1 | B`. The valid Gray code sequence forwards is `00 -> 01 -> 11 -> 10 -> 00`.
+const TRANSITIONS: [[i8; 4]; 4] = [
+    [0, 1, -1, INVALID],
+    [-1, 0, INVALID, 1],
+    [1, INVALID, 0, -1],
+    [INVALID, -1, 1, 0],
+];
+
+/// Resolution of a quadrature encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EncoderMode {
+    /// Counts once per full quadrature cycle.
+    X1 = 4,
+    /// Counts twice per full quadrature cycle.
+    X2 = 2,
+    /// Counts every edge on both channels.
+    X4 = 1,
+}
+
+/// Configuration of a quadrature encoder.
+#[derive(Debug, Clone, Copy)]
+pub struct EncoderConfig {
+    /// Resolution of the reported position.
+    pub mode: EncoderMode,
+    /// Time span used to estimate the velocity. `None` disables velocity estimation.
+    pub velocity_window: Option<Duration>,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        Self {
+            mode: EncoderMode::X4,
+            velocity_window: None,
+        }
+    }
+}
+
+/// Change of the position of a quadrature encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderEvent {
+    /// Position after the change.
+    pub position: i64,
+    /// Signed amount of counts the position changed by.
+    pub delta: i64,
+    /// Monotonic time the change got detected.
+    pub timestamp: Instant,
+}
+
+/// State shared between a `QuadratureEncoder` and its worker thread.
+#[derive(Debug)]
+struct Shared {
+    /// Position in quarter steps, independent of the selected mode.
+    quarter_steps: AtomicI64,
+    invalid_transitions: AtomicU64,
+    running: AtomicBool,
+    subscribers: Mutex<Vec<Sender<EncoderEvent>>>,
+    history: Mutex<VecDeque<(Instant, i64)>>,
+}
+
+/// A quadrature rotary encoder decoded from two input pins.
+///
+/// Both pins are watched on both edges in a background thread, every transition gets validated with a state
+/// transition table and transitions skipping a state are rejected.
+#[derive(Debug)]
+pub struct QuadratureEncoder {
+    config: EncoderConfig,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl QuadratureEncoder {
+    /// Creates an encoder from its A and B channel pins and starts decoding.
+    ///
+    /// Overrides the interrupt service routine mode of both pins to `IsrMode::Both`.
+    pub fn new(
+        pin_a: Pin<Input>,
+        pin_b: Pin<Input>,
+        config: EncoderConfig,
+    ) -> Result<Self, WiringXError> {
+        pin_a.set_isr_mode(IsrMode::Both)?;
+        pin_b.set_isr_mode(IsrMode::Both)?;
+
+        let fds = [pin_a.selectable_fd()?, pin_b.selectable_fd()?];
+
+        let shared = Arc::new(Shared {
+            quarter_steps: AtomicI64::new(0),
+            invalid_transitions: AtomicU64::new(0),
+            running: AtomicBool::new(true),
+            subscribers: Mutex::new(Vec::new()),
+            history: Mutex::new(VecDeque::new()),
+        });
+
+        let mut state = read_state(fds).map_err(WiringXError::Io)?;
+
+        let worker = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(format!(
+                    "wiringx-encoder-{}-{}",
+                    pin_a.number(),
+                    pin_b.number()
+                ))
+                .spawn(move || {
+                    // Keeps the pins reserved for as long as the encoder runs.
+                    let _pins = (pin_a, pin_b);
+
+                    while shared.running.load(Ordering::Relaxed) {
+                        let timestamp = match poll_edges(&fds, POLL_INTERVAL) {
+                            Ok(Some(timestamp)) => timestamp,
+                            Ok(None) => continue,
+                            Err(_) => break,
+                        };
+
+                        let Ok(next) = read_state(fds) else {
+                            break;
+                        };
+
+                        match TRANSITIONS[state as usize][next as usize] {
+                            0 => (),
+                            INVALID => {
+                                shared.invalid_transitions.fetch_add(1, Ordering::Relaxed);
+                            }
+                            step => shared.step(step as i64, timestamp, &config),
+                        }
+
+                        state = next;
+                    }
+                })
+                .map_err(WiringXError::Io)?
+        };
+
+        Ok(Self {
+            config,
+            shared,
+            worker: Some(worker),
+        })
+    }
+
+    /// Returns the current position in counts of the configured mode.
+    pub fn position(&self) -> i64 {
+        to_counts(
+            self.shared.quarter_steps.load(Ordering::Relaxed),
+            self.config.mode,
+        )
+    }
+
+    /// Sets the position to zero, returning the position before the reset.
+    pub fn reset(&self) -> i64 {
+        self.shared.history.lock().clear();
+
+        to_counts(
+            self.shared.quarter_steps.swap(0, Ordering::Relaxed),
+            self.config.mode,
+        )
+    }
+
+    /// Returns the number of rejected transitions in which both channels changed at once.
+    ///
+    /// A rising number means the encoder turns too fast for edges to be processed in time or the signal is noisy.
+    pub fn invalid_transitions(&self) -> u64 {
+        self.shared.invalid_transitions.load(Ordering::Relaxed)
+    }
+
+    /// Returns the estimated velocity in counts per second, averaged over the configured window.
+    ///
+    /// Returns `None` if velocity estimation is disabled in the config.
+    pub fn velocity(&self) -> Option<f64> {
+        let window = self.config.velocity_window?;
+        let now = Instant::now();
+
+        let mut history = self.shared.history.lock();
+        prune(&mut history, now, window);
+
+        let Some(&(_, oldest)) = history.front() else {
+            return Some(0.0);
+        };
+        let current = self.shared.quarter_steps.load(Ordering::Relaxed);
+
+        let steps = to_counts(current, self.config.mode) - to_counts(oldest, self.config.mode);
+
+        Some(steps as f64 / window.as_secs_f64())
+    }
+
+    /// Returns a receiver getting notified on every position change.
+    pub fn subscribe(&self) -> Receiver<EncoderEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.subscribers.lock().push(sender);
+
+        receiver
+    }
+}
+
+impl Drop for QuadratureEncoder {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Shared {
+    /// Applies a valid quarter step and notifies subscribers if the position in the configured mode changed.
+    fn step(&self, step: i64, timestamp: Instant, config: &EncoderConfig) {
+        let previous = self.quarter_steps.fetch_add(step, Ordering::Relaxed);
+        let current = previous + step;
+
+        if let Some(window) = config.velocity_window {
+            let mut history = self.history.lock();
+            history.push_back((timestamp, previous));
+            prune(&mut history, timestamp, window);
+        }
+
+        let delta = to_counts(current, config.mode) - to_counts(previous, config.mode);
+        if delta == 0 {
+            return;
+        }
+
+        let event = EncoderEvent {
+            position: to_counts(current, config.mode),
+            delta,
+            timestamp,
+        };
+
+        self.subscribers
+            .lock()
+            .retain(|subscriber| subscriber.send(event).is_ok());
+    }
+}
+
+/// Reads both channels into a two bit state.
+fn read_state(fds: [RawFd; 2]) -> std::io::Result<u8> {
+    let a = read_level(fds[0])? == Value::High;
+    let b = read_level(fds[1])? == Value::High;
+
+    Ok((a as u8) << 1 | b as u8)
+}
+
+/// Converts quarter steps into counts of the given mode.
+fn to_counts(quarter_steps: i64, mode: EncoderMode) -> i64 {
+    quarter_steps.div_euclid(mode as i64)
+}
+
+/// Removes all steps older than the window.
+fn prune(history: &mut VecDeque<(Instant, i64)>, now: Instant, window: Duration) {
+    while history
+        .front()
+        .is_some_and(|&(timestamp, _)| now.duration_since(timestamp) > window)
+    {
+        history.pop_front();
+    }
+}
@@ -0,0 +1,94 @@
+//! Pulse generation and pulse width measurement with microsecond timing.
+
+use std::{
+    hint,
+    time::{Duration, Instant},
+};
+
+use crate::timing::sleep_until;
+
+use super::{Input, InterruptTimeOut, Output, Pin, Value};
+
+impl Pin<Output> {
+    /// Drives the pin to the given level for the given duration, then to the opposite level.
+    ///
+    /// Returns the time the level was actually held, measured between both writes.
+    /// Comparing it with the requested duration shows the achieved accuracy.
+    pub fn pulse(&self, level: Value, duration: Duration) -> Duration {
+        let idle = match level {
+            Value::High => Value::Low,
+            Value::Low => Value::High,
+        };
+
+        self.write(level);
+        let start = Instant::now();
+
+        sleep_until(start + duration);
+
+        self.write(idle);
+        start.elapsed()
+    }
+}
+
+/// Result of `Pin::measure_pulse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PulseWidth {
+    /// Measured length of the pulse.
+    pub width: Duration,
+    /// Largest amount the actual length may differ from `width` by.
+    ///
+    /// Each edge is only known to lie between two samples of the pin, so this is the longer of both sample gaps.
+    pub uncertainty: Duration,
+}
+
+impl Pin<Input> {
+    /// Measures the length of the next pulse of the given level, like Arduino's `pulseIn`.
+    ///
+    /// Waits for a pulse already in progress to end, then for the pin to change to `level` and back.
+    /// The pin gets sampled in a busy loop, so the accuracy is limited by the time a single read takes, which gets
+    /// reported as the uncertainty of the measurement.
+    ///
+    /// Returns InterruptTimeOut if no complete pulse occurred within the timeout.
+    pub fn measure_pulse(
+        &self,
+        level: Value,
+        timeout: Duration,
+    ) -> Result<PulseWidth, InterruptTimeOut> {
+        let deadline = Instant::now() + timeout;
+
+        self.spin_while(|value| value == level, deadline)?;
+        let (before_start, start) = self.spin_while(|value| value != level, deadline)?;
+        let (before_end, end) = self.spin_while(|value| value == level, deadline)?;
+
+        Ok(PulseWidth {
+            width: end - start,
+            uncertainty: (start - before_start).max(end - before_end),
+        })
+    }
+
+    /// Busy-waits while the level of the pin matches the condition.
+    ///
+    /// Returns the times of the last sample matching and of the first sample not matching anymore.
+    fn spin_while(
+        &self,
+        condition: impl Fn(Value) -> bool,
+        deadline: Instant,
+    ) -> Result<(Instant, Instant), InterruptTimeOut> {
+        let mut last = Instant::now();
+
+        loop {
+            let value = self.read();
+            let now = Instant::now();
+
+            if !condition(value) {
+                return Ok((last, now));
+            }
+            if now >= deadline {
+                return Err(InterruptTimeOut);
+            }
+
+            last = now;
+            hint::spin_loop();
+        }
+    }
+}
@@ -1,6 +1,23 @@
 //! Inter-integrated circuit related objects.
 
-use std::{ffi::CString, os::fd::RawFd, path::PathBuf};
+mod address;
+mod bus;
+mod ioctl;
+mod recovery;
+mod smbus;
+
+pub use address::I2cAddress;
+pub use bus::{I2cBus, I2cDevice, RetryPolicy};
+pub use recovery::BusRecovery;
+pub use smbus::pec;
+
+use std::{
+    ffi::CString,
+    fs::OpenOptions,
+    io,
+    os::fd::{IntoRawFd, RawFd},
+    path::PathBuf,
+};
 
 use thiserror::Error;
 use wiringx_sys::{
@@ -10,30 +27,60 @@ use wiringx_sys::{
 
 use crate::{Hand, WiringXError};
 
+use ioctl::{I2cMsg, I2C_M_RD, I2C_M_TEN, I2C_RDWR_IOCTL_MAX_MSGS};
+
+/// A single segment of an I2C transaction.
+#[derive(Debug)]
+pub enum Operation<'a> {
+    /// Reads as many bytes as the buffer holds from the device.
+    Read(&'a mut [u8]),
+    /// Writes all bytes of the buffer to the device.
+    Write(&'a [u8]),
+}
+
 /// Implementations for the I2C protocol.
 #[derive(Debug)]
 pub struct I2C {
-    id: (PathBuf, i32),
-    handles: Hand<(PathBuf, i32)>,
+    id: (PathBuf, I2cAddress),
+    handles: Hand<(PathBuf, I2cAddress)>,
     fd: RawFd,
 }
 
 impl I2C {
     pub(super) fn new(
         dev: PathBuf,
-        addr: i32,
-        handles: Hand<(PathBuf, i32)>,
+        addr: I2cAddress,
+        handles: Hand<(PathBuf, I2cAddress)>,
     ) -> Result<Self, WiringXError> {
         if handles.lock().contains(&(dev.clone(), addr)) {
             return Err(WiringXError::PinUsed);
         }
 
-        let path_string = CString::new(dev.to_str().ok_or(WiringXError::Other(
-            "Path contains illegal symbols.".to_string(),
-        ))?)
-        .map_err(|e| WiringXError::Other(e.to_string()))?;
+        addr.warn_if_reserved();
 
-        let fd_result = unsafe { wiringXI2CSetup(path_string.as_ptr(), addr) };
+        // wiringX can only select 7 bit addresses.
+        let fd_result = if !addr.is_ten_bit() {
+            let path_string = CString::new(dev.to_str().ok_or(WiringXError::Other(
+                "Path contains illegal symbols.".to_string(),
+            ))?)
+            .map_err(|e| WiringXError::Other(e.to_string()))?;
+
+            unsafe { wiringXI2CSetup(path_string.as_ptr(), addr.value() as i32) }
+        } else {
+            let fd = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&dev)
+                .map_err(WiringXError::Io)?
+                .into_raw_fd();
+
+            if let Err(error) = ioctl::select(fd, addr) {
+                unsafe { libc::close(fd) };
+                return Err(WiringXError::Io(error));
+            }
+
+            fd
+        };
 
         if fd_result < 0 {
             return Err(WiringXError::Unsupported);
@@ -107,10 +154,114 @@ impl I2C {
             Ok(())
         }
     }
+
+    /// Executes all operations as one transaction, separated by repeated STARTs and ended with a single STOP.
+    ///
+    /// Uses the `I2C_RDWR` ioctl, so no other bus master can interfere between the operations.
+    pub fn transaction(&self, operations: &mut [Operation]) -> Result<(), I2CError> {
+        transfer(self.fd, self.id.1, operations)
+    }
+
+    /// Returns the address of the device.
+    pub fn address(&self) -> I2cAddress {
+        self.id.1
+    }
+
+    /// Writes the given bytes and reads into the buffer afterwards using a repeated START.
+    pub fn write_read(&self, write: &[u8], read: &mut [u8]) -> Result<(), I2CError> {
+        self.transaction(&mut [Operation::Write(write), Operation::Read(read)])
+    }
+
+    /// Reads data starting at the given 16 bit register address, as used by larger EEPROMs.
+    ///
+    /// The address gets sent most significant byte first.
+    pub fn read_reg_addr16(&self, register: u16, data: &mut [u8]) -> Result<(), I2CError> {
+        self.write_read(&register.to_be_bytes(), data)
+    }
+
+    /// Writes data starting at the given 16 bit register address, as used by larger EEPROMs.
+    ///
+    /// The address gets sent most significant byte first.
+    pub fn write_reg_addr16(&self, register: u16, data: &[u8]) -> Result<(), I2CError> {
+        let mut buffer = Vec::with_capacity(data.len() + 2);
+        buffer.extend_from_slice(&register.to_be_bytes());
+        buffer.extend_from_slice(data);
+
+        self.transaction(&mut [Operation::Write(&buffer)])
+    }
+
+    /// Sends an SMBus quick command, transferring only the read/write bit.
+    pub fn quick_command(&self, read: bool) -> Result<(), I2CError> {
+        smbus::quick_command(self.fd, read)
+    }
+
+    /// Sends a 16 bit value to the given command and returns the 16 bit answer, using an SMBus process call.
+    pub fn process_call(&self, command: u8, value: u16) -> Result<u16, I2CError> {
+        smbus::process_call(self.fd, command, value)
+    }
+
+    /// Reads an SMBus block of up to 32 bytes, whose length is sent by the device, from the given command.
+    pub fn block_read(&self, command: u8) -> Result<Vec<u8>, I2CError> {
+        smbus::block_read(self.fd, command)
+    }
+
+    /// Writes an SMBus block of up to 32 bytes, preceded by its length, to the given command.
+    pub fn block_write(&self, command: u8, data: &[u8]) -> Result<(), I2CError> {
+        smbus::block_write(self.fd, command, data)
+    }
+
+    /// Writes an SMBus block and reads the block answer, using an SMBus block process call.
+    pub fn block_process_call(&self, command: u8, data: &[u8]) -> Result<Vec<u8>, I2CError> {
+        smbus::block_process_call(self.fd, command, data)
+    }
+
+    /// Enables or disables SMBus Packet Error Checking.
+    ///
+    /// While enabled the kernel appends a PEC byte to SMBus transfers and verifies the PEC of received data,
+    /// returning `I2CError::Pec` on mismatch.
+    pub fn set_pec(&self, enabled: bool) -> Result<(), I2CError> {
+        ioctl::set_pec(self.fd, enabled)?;
+
+        Ok(())
+    }
+}
+
+/// Executes the operations as a single `I2C_RDWR` transfer to the given address.
+fn transfer(fd: RawFd, addr: I2cAddress, operations: &mut [Operation]) -> Result<(), I2CError> {
+    if operations.is_empty() {
+        return Ok(());
+    }
+
+    if operations.len() > I2C_RDWR_IOCTL_MAX_MSGS {
+        return Err(I2CError::InvalidTransaction);
+    }
+
+    let mut messages = Vec::with_capacity(operations.len());
+    for operation in operations.iter_mut() {
+        let (mut flags, buffer, len) = match operation {
+            Operation::Read(buffer) => (I2C_M_RD, buffer.as_mut_ptr(), buffer.len()),
+            // The kernel does not write to the buffers of write messages.
+            Operation::Write(buffer) => (0, buffer.as_ptr() as *mut u8, buffer.len()),
+        };
+
+        if addr.is_ten_bit() {
+            flags |= I2C_M_TEN;
+        }
+
+        messages.push(I2cMsg {
+            addr: addr.value(),
+            flags,
+            len: u16::try_from(len).map_err(|_| I2CError::InvalidTransaction)?,
+            buf: buffer,
+        });
+    }
+
+    ioctl::rdwr(fd, &mut messages).map_err(I2CError::from)
 }
 
 impl Drop for I2C {
     fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
         self.handles.lock().remove(&self.id);
     }
 }
@@ -124,4 +275,40 @@ pub enum I2CError {
     /// Gets returned if for some reason the write operation fails.
     #[error("Failed to write to I2C device.")]
     Write,
+    /// The device did not acknowledge its address or a written byte.
+    #[error("The I2C device did not acknowledge.")]
+    Nack,
+    /// The bus did not complete the transfer in time.
+    #[error("The I2C transfer timed out.")]
+    Timeout,
+    /// Another master took over the bus during the transfer.
+    #[error("Lost arbitration on the I2C bus.")]
+    ArbitrationLost,
+    /// A transaction contained too many operations, a buffer longer than 65535 bytes or an SMBus block longer than
+    /// 32 bytes.
+    #[error("The I2C transaction is not valid.")]
+    InvalidTransaction,
+    /// The Packet Error Code of received SMBus data did not match.
+    #[error("SMBus packet error check failed.")]
+    Pec,
+    /// The address does not fit into 7 or 10 bits.
+    #[error("The I2C address is out of range.")]
+    InvalidAddress,
+    /// Any other failure of an I2C transfer, holding the OS error code.
+    #[error("I2C transfer failed: {}", io::Error::from_raw_os_error(*.0))]
+    Transfer(i32),
+}
+
+impl From<io::Error> for I2CError {
+    fn from(error: io::Error) -> Self {
+        match error.raw_os_error() {
+            Some(libc::ENXIO | libc::EREMOTEIO) => Self::Nack,
+            Some(libc::ETIMEDOUT) => Self::Timeout,
+            Some(libc::EAGAIN) => Self::ArbitrationLost,
+            Some(libc::EINVAL) => Self::InvalidTransaction,
+            Some(libc::EBADMSG) => Self::Pec,
+            Some(code) => Self::Transfer(code),
+            None => Self::Transfer(libc::EIO),
+        }
+    }
 }
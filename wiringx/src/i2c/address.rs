@@ -0,0 +1,100 @@
+//! Validated I2C device addresses.
+
+use std::fmt;
+
+use super::I2CError;
+
+/// Address of a device on an I2C bus.
+///
+/// Can only be created through `seven_bit` and `ten_bit`, so every address is known to be in range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct I2cAddress(Kind);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Kind {
+    /// A regular 7 bit address from `0x00` to `0x7f`.
+    SevenBit(u8),
+    /// An extended 10 bit address from `0x000` to `0x3ff`.
+    TenBit(u16),
+}
+
+impl I2cAddress {
+    /// Creates a 7 bit address, failing if it does not fit into 7 bits.
+    ///
+    /// Reserved addresses are accepted, `reserved_purpose` tells if an address is reserved. Opening a device at a
+    /// reserved address logs a warning.
+    pub fn seven_bit(addr: u8) -> Result<Self, I2CError> {
+        if addr > 0x7f {
+            return Err(I2CError::InvalidAddress);
+        }
+
+        Ok(Self(Kind::SevenBit(addr)))
+    }
+
+    /// Creates a 10 bit address, failing if it does not fit into 10 bits.
+    ///
+    /// Requires an adapter supporting 10 bit addressing.
+    pub fn ten_bit(addr: u16) -> Result<Self, I2CError> {
+        if addr > 0x3ff {
+            return Err(I2CError::InvalidAddress);
+        }
+
+        Ok(Self(Kind::TenBit(addr)))
+    }
+
+    /// Returns the numeric value of the address.
+    pub fn value(&self) -> u16 {
+        match self.0 {
+            Kind::SevenBit(addr) => addr as u16,
+            Kind::TenBit(addr) => addr,
+        }
+    }
+
+    /// Returns true if this is a 10 bit address.
+    pub fn is_ten_bit(&self) -> bool {
+        matches!(self.0, Kind::TenBit(_))
+    }
+
+    /// Returns what a reserved 7 bit address is used for by the I2C specification, or `None` for regular addresses.
+    ///
+    /// Devices should not use reserved addresses, talking to them may confuse other devices on the bus.
+    pub fn reserved_purpose(&self) -> Option<&'static str> {
+        let Kind::SevenBit(addr) = self.0 else {
+            return None;
+        };
+
+        match addr {
+            0x00 => Some("general call or START byte"),
+            0x01 => Some("CBUS address"),
+            0x02 => Some("reserved for different bus formats"),
+            0x03 => Some("reserved for future purposes"),
+            0x04..=0x07 => Some("Hs-mode master code"),
+            0x78..=0x7b => Some("10 bit addressing prefix"),
+            0x7c..=0x7f => Some("device ID"),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the I2C specification reserves this address. See `reserved_purpose`.
+    pub fn is_reserved(&self) -> bool {
+        self.reserved_purpose().is_some()
+    }
+
+    /// Logs a warning if a device gets opened at a reserved address.
+    pub(crate) fn warn_if_reserved(&self) {
+        if let Some(purpose) = self.reserved_purpose() {
+            log::warn!(
+                "Opening I2C device at reserved address {self}, which is used for {purpose}."
+            );
+        }
+    }
+}
+
+impl fmt::Display for I2cAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Kind::SevenBit(addr) => write!(f, "0x{addr:02x}"),
+            Kind::TenBit(addr) => write!(f, "0x{addr:03x} (10 bit)"),
+        }
+    }
+}
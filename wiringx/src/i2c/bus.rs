@@ -0,0 +1,460 @@
+//! Shared I2C buses serving many devices.
+
+use std::{
+    collections::HashSet,
+    fs::OpenOptions,
+    io,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use wiringx_sys::{
+    wiringXI2CRead, wiringXI2CReadReg16, wiringXI2CReadReg8, wiringXI2CWrite, wiringXI2CWriteReg16,
+    wiringXI2CWriteReg8,
+};
+
+use crate::{Hand, WiringX, WiringXError};
+
+use super::{
+    ioctl::{
+        self, I2cSmbusData, I2C_FUNC_SMBUS_QUICK, I2C_FUNC_SMBUS_READ_BYTE, I2C_SMBUS_BYTE,
+        I2C_SMBUS_QUICK, I2C_SMBUS_READ, I2C_SMBUS_WRITE,
+    },
+    recovery::{self, BusRecovery},
+    smbus, transfer, I2CError, I2cAddress, Operation,
+};
+
+/// First address probed by `I2cBus::scan`, lower addresses are reserved.
+const SCAN_FIRST: u8 = 0x08;
+/// Last address probed by `I2cBus::scan`, higher addresses are reserved.
+const SCAN_LAST: u8 = 0x77;
+
+/// How operations of `I2cDevice`s get retried after transient failures.
+///
+/// Lost arbitration and missing acknowledgements, for example from an EEPROM busy with a write cycle, get retried.
+/// The wait between attempts starts at `backoff` and doubles after every attempt up to `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt. Zero disables retrying.
+    pub retries: u32,
+    /// Wait before the first retry.
+    pub backoff: Duration,
+    /// Upper limit of the wait between retries.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(50),
+        }
+    }
+}
+
+/// State shared between a bus and all of its devices.
+#[derive(Debug)]
+struct BusInner {
+    dev: PathBuf,
+    fd: OwnedFd,
+    /// Current settings of the file descriptor. Locking it grants exclusive access to the bus.
+    selection: Mutex<Selection>,
+    devices: Mutex<HashSet<I2cAddress>>,
+    retry_policy: Mutex<RetryPolicy>,
+    handles: Hand<PathBuf>,
+}
+
+/// Per file descriptor settings of the kernel, switched between devices.
+#[derive(Debug, Default)]
+struct Selection {
+    /// Slave address the file descriptor currently points to.
+    addr: Option<I2cAddress>,
+    /// Whether SMBus Packet Error Checking is enabled.
+    pec: bool,
+}
+
+impl BusInner {
+    /// Runs the function on the bus file descriptor after selecting the address and PEC setting, while holding the
+    /// bus lock.
+    fn with_addr<R>(
+        &self,
+        addr: I2cAddress,
+        pec: bool,
+        f: impl FnOnce(RawFd) -> R,
+    ) -> io::Result<R> {
+        let mut selection = self.selection.lock();
+        let fd = self.fd.as_raw_fd();
+
+        if selection.addr != Some(addr) {
+            // Forget the selection on failure, the kernel state is unknown.
+            selection.addr = None;
+            ioctl::select(fd, addr)?;
+            selection.addr = Some(addr);
+        }
+
+        if selection.pec != pec {
+            ioctl::set_pec(fd, pec)?;
+            selection.pec = pec;
+        }
+
+        Ok(f(fd))
+    }
+}
+
+impl Drop for BusInner {
+    fn drop(&mut self) {
+        self.handles.lock().remove(&self.dev);
+    }
+}
+
+/// An I2C bus like `/dev/i2c-1`, owning a single file descriptor for all devices on it.
+///
+/// Cloning the bus is cheap and returns a handle to the same bus. The file descriptor gets closed once the bus and
+/// all of its devices are dropped.
+#[derive(Debug, Clone)]
+pub struct I2cBus {
+    inner: Arc<BusInner>,
+}
+
+impl I2cBus {
+    pub(crate) fn new(dev: PathBuf, handles: Hand<PathBuf>) -> Result<Self, WiringXError> {
+        if handles.lock().contains(&dev) {
+            return Err(WiringXError::PinUsed);
+        }
+
+        let fd = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&dev)
+            .map_err(WiringXError::Io)?
+            .into();
+
+        handles.lock().insert(dev.clone());
+
+        Ok(Self {
+            inner: Arc::new(BusInner {
+                dev,
+                fd,
+                selection: Mutex::new(Selection::default()),
+                devices: Mutex::new(HashSet::new()),
+                retry_policy: Mutex::new(RetryPolicy::default()),
+                handles,
+            }),
+        })
+    }
+
+    /// Returns the device path of this bus.
+    pub fn path(&self) -> &Path {
+        &self.inner.dev
+    }
+
+    /// Returns the raw file descriptor of this bus.
+    pub fn get_fd(&self) -> RawFd {
+        self.inner.fd.as_raw_fd()
+    }
+
+    /// Returns a handle to the device with the given address on this bus.
+    ///
+    /// Only one handle per address can exist at a time.
+    pub fn device(&self, addr: I2cAddress) -> Result<I2cDevice, WiringXError> {
+        if !self.inner.devices.lock().insert(addr) {
+            return Err(WiringXError::PinUsed);
+        }
+
+        addr.warn_if_reserved();
+
+        Ok(I2cDevice {
+            bus: self.inner.clone(),
+            addr,
+            pec: false,
+        })
+    }
+
+    /// Executes the operations on the device with the given address as one transaction.
+    ///
+    /// See `I2cDevice::transaction`.
+    pub fn transaction(
+        &self,
+        addr: I2cAddress,
+        operations: &mut [Operation],
+    ) -> Result<(), I2CError> {
+        let _lock = self.inner.selection.lock();
+
+        transfer(self.get_fd(), addr, operations)
+    }
+
+    /// Sets how operations of devices on this bus get retried after transient failures.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        *self.inner.retry_policy.lock() = policy;
+    }
+
+    /// Returns how operations of devices on this bus get retried after transient failures.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        *self.inner.retry_policy.lock()
+    }
+
+    /// Sets the time the adapter waits for a transfer to complete before giving up.
+    ///
+    /// The kernel works with a resolution of 10 milliseconds, shorter timeouts get rounded up.
+    pub fn set_timeout(&self, timeout: Duration) -> Result<(), I2CError> {
+        let _lock = self.inner.selection.lock();
+        let units = timeout.as_millis().div_ceil(10).max(1) as libc::c_ulong;
+
+        ioctl::set_timeout(self.get_fd(), units)?;
+
+        Ok(())
+    }
+
+    /// Sets how often the adapter itself retries a transfer when a device does not acknowledge its address.
+    ///
+    /// Not all adapters support this. Unlike `RetryPolicy` the retries happen inside the kernel without backoff.
+    pub fn set_retries(&self, retries: u32) -> Result<(), I2CError> {
+        let _lock = self.inner.selection.lock();
+
+        ioctl::set_retries(self.get_fd(), retries as libc::c_ulong)?;
+
+        Ok(())
+    }
+
+    /// Frees the bus when a device holds SDA low, for example after a brownout interrupted a transfer.
+    ///
+    /// Temporarily switches the bus pins to GPIO, clocks out up to nine pulses until the device releases SDA and
+    /// issues a STOP condition. Afterwards the pin multiplexer registers given in `recovery` get restored, handing the
+    /// pins back to the I2C controller.
+    /// Returns true if SDA got released.
+    pub fn recover(&self, wiringx: &WiringX, recovery: &BusRecovery) -> Result<bool, WiringXError> {
+        let _lock = self.inner.selection.lock();
+
+        recovery::recover(wiringx, recovery)
+    }
+
+    /// Returns the addresses of all devices responding on the bus, like `i2cdetect`.
+    ///
+    /// Scans the 7 bit addresses from `0x08` to `0x77`, see `probe`.
+    pub fn scan(&self) -> Result<Vec<I2cAddress>, I2CError> {
+        let funcs = ioctl::funcs(self.get_fd())?;
+
+        let mut found = Vec::new();
+        for addr in (SCAN_FIRST..=SCAN_LAST).filter_map(|addr| I2cAddress::seven_bit(addr).ok()) {
+            if self.probe_with(addr, funcs)? {
+                found.push(addr);
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Checks if a device responds at the given address.
+    ///
+    /// Addresses where EEPROMs and write protection registers usually live get probed with a read, all others with
+    /// an SMBus quick write, as `i2cdetect` does. Addresses claimed by a kernel driver count as present.
+    pub fn probe(&self, addr: I2cAddress) -> Result<bool, I2CError> {
+        let funcs = ioctl::funcs(self.get_fd())?;
+
+        self.probe_with(addr, funcs)
+    }
+
+    fn probe_with(&self, addr: I2cAddress, funcs: u64) -> Result<bool, I2CError> {
+        let read_probe = !addr.is_ten_bit() && matches!(addr.value(), 0x30..=0x37 | 0x50..=0x5f);
+
+        let result = if (read_probe && funcs & I2C_FUNC_SMBUS_READ_BYTE != 0)
+            || funcs & I2C_FUNC_SMBUS_QUICK == 0
+        {
+            let mut data = I2cSmbusData::default();
+            self.inner.with_addr(addr, false, |fd| {
+                ioctl::smbus_access(fd, I2C_SMBUS_READ, 0, I2C_SMBUS_BYTE, Some(&mut data))
+            })
+        } else {
+            self.inner.with_addr(addr, false, |fd| {
+                ioctl::smbus_access(fd, I2C_SMBUS_WRITE, 0, I2C_SMBUS_QUICK, None)
+            })
+        };
+
+        match result.and_then(|result| result) {
+            Ok(()) => Ok(true),
+            Err(error) if error.raw_os_error() == Some(libc::EBUSY) => Ok(true),
+            Err(error) => match I2CError::from(error) {
+                I2CError::Nack | I2CError::Timeout => Ok(false),
+                error => Err(error),
+            },
+        }
+    }
+}
+
+/// A device on a shared `I2cBus`.
+///
+/// Every call points the bus to the address of this device and runs while holding the bus lock, so calls from
+/// different threads never interleave.
+#[derive(Debug)]
+pub struct I2cDevice {
+    bus: Arc<BusInner>,
+    addr: I2cAddress,
+    pec: bool,
+}
+
+impl I2cDevice {
+    /// Returns the address of this device.
+    pub fn address(&self) -> I2cAddress {
+        self.addr
+    }
+
+    /// Returns a handle to the bus this device is on.
+    pub fn bus(&self) -> I2cBus {
+        I2cBus {
+            inner: self.bus.clone(),
+        }
+    }
+
+    /// Runs the function on the bus file descriptor after selecting this device, while holding the bus lock.
+    ///
+    /// Transient failures get retried according to the retry policy of the bus. The bus lock gets released during
+    /// the backoff, so other devices can use the bus in the meantime.
+    fn with_device<R>(
+        &self,
+        mut f: impl FnMut(RawFd) -> Result<R, I2CError>,
+    ) -> Result<R, I2CError> {
+        let policy = *self.bus.retry_policy.lock();
+        let mut backoff = policy.backoff;
+        let mut attempt = 0;
+
+        loop {
+            let result = self
+                .bus
+                .with_addr(self.addr, self.pec, &mut f)
+                .map_err(I2CError::from)
+                .and_then(|result| result);
+
+            match result {
+                Err(I2CError::Nack | I2CError::ArbitrationLost) if attempt < policy.retries => {
+                    attempt += 1;
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(policy.max_backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Reads one byte of data.
+    pub fn read(&self) -> Result<u8, I2CError> {
+        let result = self.with_device(|fd| check(unsafe { wiringXI2CRead(fd) }))?;
+
+        Ok(result as u8)
+    }
+
+    /// Reads one byte of data from the given register.
+    pub fn read_reg8(&self, reg: i32) -> Result<u8, I2CError> {
+        let result = self.with_device(|fd| check(unsafe { wiringXI2CReadReg8(fd, reg) }))?;
+
+        Ok(result as u8)
+    }
+
+    /// Reads two bytes of data from the given register.
+    pub fn read_reg16(&self, reg: i32) -> Result<u16, I2CError> {
+        let result = self.with_device(|fd| check(unsafe { wiringXI2CReadReg16(fd, reg) }))?;
+
+        Ok(result as u16)
+    }
+
+    /// Writes the address of the register, preparing data writes on the device.
+    pub fn write(&self, register: i32) -> Result<(), I2CError> {
+        self.with_device(|fd| check(unsafe { wiringXI2CWrite(fd, register) }))?;
+
+        Ok(())
+    }
+
+    /// Writes one byte of data to the given register.
+    pub fn write_reg8(&self, register: i32, value: u8) -> Result<(), I2CError> {
+        self.with_device(|fd| check(unsafe { wiringXI2CWriteReg8(fd, register, value as i32) }))?;
+
+        Ok(())
+    }
+
+    /// Writes two bytes of data to the given register.
+    pub fn write_reg16(&self, register: i32, value: u16) -> Result<(), I2CError> {
+        self.with_device(|fd| check(unsafe { wiringXI2CWriteReg16(fd, register, value as i32) }))?;
+
+        Ok(())
+    }
+
+    /// Executes all operations as one transaction, separated by repeated STARTs and ended with a single STOP.
+    pub fn transaction(&self, operations: &mut [Operation]) -> Result<(), I2CError> {
+        self.with_device(|fd| transfer(fd, self.addr, operations))
+    }
+
+    /// Writes the given bytes and reads into the buffer afterwards using a repeated START.
+    pub fn write_read(&self, write: &[u8], read: &mut [u8]) -> Result<(), I2CError> {
+        self.transaction(&mut [Operation::Write(write), Operation::Read(read)])
+    }
+
+    /// Reads data starting at the given 16 bit register address, as used by larger EEPROMs.
+    ///
+    /// The address gets sent most significant byte first.
+    pub fn read_reg_addr16(&self, register: u16, data: &mut [u8]) -> Result<(), I2CError> {
+        self.write_read(&register.to_be_bytes(), data)
+    }
+
+    /// Writes data starting at the given 16 bit register address, as used by larger EEPROMs.
+    ///
+    /// The address gets sent most significant byte first.
+    pub fn write_reg_addr16(&self, register: u16, data: &[u8]) -> Result<(), I2CError> {
+        let mut buffer = Vec::with_capacity(data.len() + 2);
+        buffer.extend_from_slice(&register.to_be_bytes());
+        buffer.extend_from_slice(data);
+
+        self.transaction(&mut [Operation::Write(&buffer)])
+    }
+
+    /// Sends an SMBus quick command, transferring only the read/write bit.
+    pub fn quick_command(&self, read: bool) -> Result<(), I2CError> {
+        self.with_device(|fd| smbus::quick_command(fd, read))
+    }
+
+    /// Sends a 16 bit value to the given command and returns the 16 bit answer, using an SMBus process call.
+    pub fn process_call(&self, command: u8, value: u16) -> Result<u16, I2CError> {
+        self.with_device(|fd| smbus::process_call(fd, command, value))
+    }
+
+    /// Reads an SMBus block of up to 32 bytes, whose length is sent by the device, from the given command.
+    pub fn block_read(&self, command: u8) -> Result<Vec<u8>, I2CError> {
+        self.with_device(|fd| smbus::block_read(fd, command))
+    }
+
+    /// Writes an SMBus block of up to 32 bytes, preceded by its length, to the given command.
+    pub fn block_write(&self, command: u8, data: &[u8]) -> Result<(), I2CError> {
+        self.with_device(|fd| smbus::block_write(fd, command, data))
+    }
+
+    /// Writes an SMBus block and reads the block answer, using an SMBus block process call.
+    pub fn block_process_call(&self, command: u8, data: &[u8]) -> Result<Vec<u8>, I2CError> {
+        self.with_device(|fd| smbus::block_process_call(fd, command, data))
+    }
+
+    /// Enables or disables SMBus Packet Error Checking for this device.
+    ///
+    /// While enabled the kernel appends a PEC byte to SMBus transfers and verifies the PEC of received data,
+    /// returning `I2CError::Pec` on mismatch. Other devices on the bus are not affected.
+    pub fn set_pec(&mut self, enabled: bool) {
+        self.pec = enabled;
+    }
+}
+
+/// Turns the C style result of a wiringX I2C function into the error reported by the kernel.
+fn check(result: i32) -> Result<i32, I2CError> {
+    if result < 0 {
+        Err(I2CError::from(io::Error::last_os_error()))
+    } else {
+        Ok(result)
+    }
+}
+
+impl Drop for I2cDevice {
+    fn drop(&mut self) {
+        self.bus.devices.lock().remove(&self.addr);
+    }
+}
@@ -0,0 +1,213 @@
+//! Definitions of the Linux i2c-dev userspace interface.
+
+use std::{io, os::fd::RawFd};
+
+use super::I2cAddress;
+
+/// Sets how often the adapter retries when a slave does not acknowledge its address.
+pub(crate) const I2C_RETRIES: u32 = 0x0701;
+
+/// Sets the transfer timeout in units of 10 milliseconds.
+pub(crate) const I2C_TIMEOUT: u32 = 0x0702;
+
+/// Selects the slave address following reads and writes go to.
+pub(crate) const I2C_SLAVE: u32 = 0x0703;
+
+/// Switches between 7 and 10 bit slave addresses.
+pub(crate) const I2C_TENBIT: u32 = 0x0704;
+
+/// Returns the functionality mask of the adapter.
+pub(crate) const I2C_FUNCS: u32 = 0x0705;
+
+/// Combined read and write transfer with only one STOP.
+pub(crate) const I2C_RDWR: u32 = 0x0707;
+
+/// Maximum number of messages in a single `I2C_RDWR` transfer.
+pub(crate) const I2C_RDWR_IOCTL_MAX_MSGS: usize = 42;
+
+/// Enables or disables SMBus Packet Error Checking.
+pub(crate) const I2C_PEC: u32 = 0x0708;
+
+/// SMBus transfer.
+pub(crate) const I2C_SMBUS: u32 = 0x0720;
+
+/// Adapter supports the SMBus quick command.
+pub(crate) const I2C_FUNC_SMBUS_QUICK: u64 = 0x0001_0000;
+/// Adapter supports reading a single byte without a command.
+pub(crate) const I2C_FUNC_SMBUS_READ_BYTE: u64 = 0x0002_0000;
+
+/// Direction of an SMBus transfer reading from the slave.
+pub(crate) const I2C_SMBUS_READ: u8 = 1;
+/// Direction of an SMBus transfer writing to the slave.
+pub(crate) const I2C_SMBUS_WRITE: u8 = 0;
+
+/// SMBus transfer types.
+pub(crate) const I2C_SMBUS_QUICK: u32 = 0;
+pub(crate) const I2C_SMBUS_BYTE: u32 = 1;
+pub(crate) const I2C_SMBUS_PROC_CALL: u32 = 4;
+pub(crate) const I2C_SMBUS_BLOCK_DATA: u32 = 5;
+pub(crate) const I2C_SMBUS_BLOCK_PROC_CALL: u32 = 7;
+
+/// Maximum number of data bytes in an SMBus block transfer.
+pub(crate) const I2C_SMBUS_BLOCK_MAX: usize = 32;
+
+/// Message flag marking a read from the slave.
+pub(crate) const I2C_M_RD: u16 = 0x0001;
+/// Message flag marking a 10 bit slave address.
+pub(crate) const I2C_M_TEN: u16 = 0x0010;
+
+/// A single segment of an `I2C_RDWR` transfer, starting with a (repeated) START.
+#[repr(C)]
+pub(crate) struct I2cMsg {
+    pub(crate) addr: u16,
+    pub(crate) flags: u16,
+    pub(crate) len: u16,
+    pub(crate) buf: *mut u8,
+}
+
+/// Argument of the `I2C_RDWR` ioctl.
+#[repr(C)]
+pub(crate) struct I2cRdwrIoctlData {
+    pub(crate) msgs: *mut I2cMsg,
+    pub(crate) nmsgs: u32,
+}
+
+/// Data of an SMBus transfer. For blocks the first byte holds the length.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) union I2cSmbusData {
+    pub(crate) byte: u8,
+    pub(crate) word: u16,
+    pub(crate) block: [u8; I2C_SMBUS_BLOCK_MAX + 2],
+}
+
+impl Default for I2cSmbusData {
+    fn default() -> Self {
+        Self {
+            block: [0; I2C_SMBUS_BLOCK_MAX + 2],
+        }
+    }
+}
+
+/// Argument of the `I2C_SMBUS` ioctl.
+#[repr(C)]
+pub(crate) struct I2cSmbusIoctlData {
+    pub(crate) read_write: u8,
+    pub(crate) command: u8,
+    pub(crate) size: u32,
+    pub(crate) data: *mut I2cSmbusData,
+}
+
+/// Issues a combined transfer of all messages on the given I2C file descriptor.
+pub(crate) fn rdwr(fd: RawFd, messages: &mut [I2cMsg]) -> io::Result<()> {
+    let mut data = I2cRdwrIoctlData {
+        msgs: messages.as_mut_ptr(),
+        nmsgs: messages.len() as u32,
+    };
+
+    let result = unsafe { libc::ioctl(fd, I2C_RDWR as _, &mut data) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Points the given I2C file descriptor to the slave address.
+pub(crate) fn set_slave(fd: RawFd, addr: u16) -> io::Result<()> {
+    let result = unsafe { libc::ioctl(fd, I2C_SLAVE as _, addr as libc::c_ulong) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Switches the given I2C file descriptor between 7 and 10 bit slave addresses.
+pub(crate) fn set_ten_bit(fd: RawFd, enabled: bool) -> io::Result<()> {
+    let result = unsafe { libc::ioctl(fd, I2C_TENBIT as _, enabled as libc::c_ulong) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Selects the addressing mode and slave address on the given I2C file descriptor.
+pub(crate) fn select(fd: RawFd, addr: I2cAddress) -> io::Result<()> {
+    set_ten_bit(fd, addr.is_ten_bit())?;
+    set_slave(fd, addr.value())
+}
+
+/// Returns the functionality mask of the adapter behind the given I2C file descriptor.
+pub(crate) fn funcs(fd: RawFd) -> io::Result<u64> {
+    let mut funcs: libc::c_ulong = 0;
+
+    let result = unsafe { libc::ioctl(fd, I2C_FUNCS as _, &mut funcs) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(funcs as u64)
+    }
+}
+
+/// Issues an SMBus transfer of the given type to the currently selected slave.
+pub(crate) fn smbus_access(
+    fd: RawFd,
+    read_write: u8,
+    command: u8,
+    size: u32,
+    data: Option<&mut I2cSmbusData>,
+) -> io::Result<()> {
+    let mut args = I2cSmbusIoctlData {
+        read_write,
+        command,
+        size,
+        data: data.map_or(std::ptr::null_mut(), |data| data as *mut I2cSmbusData),
+    };
+
+    let result = unsafe { libc::ioctl(fd, I2C_SMBUS as _, &mut args) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Enables or disables Packet Error Checking for SMBus transfers on the given I2C file descriptor.
+pub(crate) fn set_pec(fd: RawFd, enabled: bool) -> io::Result<()> {
+    let result = unsafe { libc::ioctl(fd, I2C_PEC as _, enabled as libc::c_ulong) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Sets the transfer timeout of the adapter in units of 10 milliseconds.
+pub(crate) fn set_timeout(fd: RawFd, units: libc::c_ulong) -> io::Result<()> {
+    let result = unsafe { libc::ioctl(fd, I2C_TIMEOUT as _, units) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Sets the number of retries of the adapter when a slave does not acknowledge its address.
+pub(crate) fn set_retries(fd: RawFd, retries: libc::c_ulong) -> io::Result<()> {
+    let result = unsafe { libc::ioctl(fd, I2C_RETRIES as _, retries) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Recovery of I2C buses blocked by a device holding SDA low.
+
+use std::{
+    fs::OpenOptions,
+    io,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    ptr,
+    time::Duration,
+};
+
+use crate::{
+    gpio::{Input, Output, Value},
+    timing::sleep_precise,
+    WiringX, WiringXError,
+};
+
+/// Half of a clock period at the standard mode speed of 100 kHz.
+const HALF_PERIOD: Duration = Duration::from_micros(5);
+
+/// Number of clock pulses after which every device has shifted out the rest of an interrupted byte.
+const RECOVERY_PULSES: usize = 9;
+
+/// Pins of an I2C bus used to free it when a device holds SDA low, for example after a brownout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusRecovery {
+    /// GPIO number of the clock line.
+    pub scl: i32,
+    /// GPIO number of the data line.
+    pub sda: i32,
+    /// Physical address of the pin multiplexer register of the clock line.
+    ///
+    /// The register gets saved before the pin is switched to GPIO and restored afterwards, giving the pin back to
+    /// the I2C controller.
+    pub scl_mux_register: u64,
+    /// Physical address of the pin multiplexer register of the data line. See `scl_mux_register`.
+    pub sda_mux_register: u64,
+}
+
+/// Clocks out a stuck device and issues a STOP condition by driving the bus lines as GPIOs.
+///
+/// Returns true if the device released SDA.
+pub(crate) fn recover(wiringx: &WiringX, recovery: &BusRecovery) -> Result<bool, WiringXError> {
+    let registers = [recovery.scl_mux_register, recovery.sda_mux_register];
+
+    let mut saved = [0; 2];
+    for (saved, register) in saved.iter_mut().zip(registers) {
+        *saved = read_register(register).map_err(WiringXError::Io)?;
+    }
+
+    let result = clock_out(wiringx, recovery);
+
+    // Without the I2C function the bus would stay unusable, so this happens even if the recovery failed.
+    for (value, register) in saved.into_iter().zip(registers) {
+        write_register(register, value).map_err(WiringXError::Io)?;
+    }
+
+    result
+}
+
+fn clock_out(wiringx: &WiringX, recovery: &BusRecovery) -> Result<bool, WiringXError> {
+    let scl = wiringx.gpio_pin::<Output>(recovery.scl)?;
+    let sda = wiringx.gpio_pin::<Input>(recovery.sda)?;
+
+    scl.write(Value::High);
+    sleep_precise(HALF_PERIOD);
+
+    for _ in 0..RECOVERY_PULSES {
+        if sda.read() == Value::High {
+            break;
+        }
+
+        scl.write(Value::Low);
+        sleep_precise(HALF_PERIOD);
+        scl.write(Value::High);
+        sleep_precise(HALF_PERIOD);
+    }
+
+    let released = sda.read() == Value::High;
+    drop(sda);
+
+    // A STOP condition is a rising edge on SDA while SCL is high.
+    let sda = wiringx.gpio_pin::<Output>(recovery.sda)?;
+    scl.write(Value::Low);
+    sleep_precise(HALF_PERIOD);
+    sda.write(Value::Low);
+    sleep_precise(HALF_PERIOD);
+    scl.write(Value::High);
+    sleep_precise(HALF_PERIOD);
+    sda.write(Value::High);
+    sleep_precise(HALF_PERIOD);
+
+    Ok(released)
+}
+
+fn read_register(address: u64) -> io::Result<u32> {
+    with_register(address, |register| unsafe { ptr::read_volatile(register) })
+}
+
+fn write_register(address: u64, value: u32) -> io::Result<()> {
+    with_register(address, |register| unsafe {
+        ptr::write_volatile(register, value)
+    })
+}
+
+/// Maps the page containing the 32 bit register at the physical address from `/dev/mem` and runs the function on it.
+fn with_register<R>(address: u64, f: impl FnOnce(*mut u32) -> R) -> io::Result<R> {
+    if !address.is_multiple_of(4) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Register addresses must be 4 byte aligned.",
+        ));
+    }
+
+    let mem = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_SYNC)
+        .open("/dev/mem")?;
+
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+    let page = address & !(page_size - 1);
+
+    let map = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            page_size as usize,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            mem.as_raw_fd(),
+            page as libc::off_t,
+        )
+    };
+
+    if map == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+
+    let register = unsafe { (map as *mut u8).add((address - page) as usize) as *mut u32 };
+    let result = f(register);
+
+    unsafe { libc::munmap(map, page_size as usize) };
+
+    Ok(result)
+}
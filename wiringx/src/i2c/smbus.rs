@@ -0,0 +1,119 @@
+//! System management bus protocol transfers through the `I2C_SMBUS` ioctl.
+
+use std::os::fd::RawFd;
+
+use super::{
+    ioctl::{
+        self, I2cSmbusData, I2C_SMBUS_BLOCK_DATA, I2C_SMBUS_BLOCK_MAX, I2C_SMBUS_BLOCK_PROC_CALL,
+        I2C_SMBUS_PROC_CALL, I2C_SMBUS_QUICK, I2C_SMBUS_READ, I2C_SMBUS_WRITE,
+    },
+    I2CError,
+};
+
+/// Calculates the SMBus Packet Error Code, a CRC-8 with the polynomial `x^8 + x^2 + x + 1`.
+///
+/// The PEC covers every byte of a transfer including the address bytes with their read/write bit.
+pub fn pec(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+pub(crate) fn quick_command(fd: RawFd, read: bool) -> Result<(), I2CError> {
+    let read_write = if read {
+        I2C_SMBUS_READ
+    } else {
+        I2C_SMBUS_WRITE
+    };
+
+    ioctl::smbus_access(fd, read_write, 0, I2C_SMBUS_QUICK, None)?;
+
+    Ok(())
+}
+
+pub(crate) fn process_call(fd: RawFd, command: u8, value: u16) -> Result<u16, I2CError> {
+    let mut data = I2cSmbusData { word: value };
+
+    ioctl::smbus_access(
+        fd,
+        I2C_SMBUS_WRITE,
+        command,
+        I2C_SMBUS_PROC_CALL,
+        Some(&mut data),
+    )?;
+
+    Ok(unsafe { data.word })
+}
+
+pub(crate) fn block_read(fd: RawFd, command: u8) -> Result<Vec<u8>, I2CError> {
+    let mut data = I2cSmbusData::default();
+
+    ioctl::smbus_access(
+        fd,
+        I2C_SMBUS_READ,
+        command,
+        I2C_SMBUS_BLOCK_DATA,
+        Some(&mut data),
+    )?;
+
+    Ok(block_of(&data))
+}
+
+pub(crate) fn block_write(fd: RawFd, command: u8, values: &[u8]) -> Result<(), I2CError> {
+    let mut data = block_data(values)?;
+
+    ioctl::smbus_access(
+        fd,
+        I2C_SMBUS_WRITE,
+        command,
+        I2C_SMBUS_BLOCK_DATA,
+        Some(&mut data),
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn block_process_call(
+    fd: RawFd,
+    command: u8,
+    values: &[u8],
+) -> Result<Vec<u8>, I2CError> {
+    let mut data = block_data(values)?;
+
+    ioctl::smbus_access(
+        fd,
+        I2C_SMBUS_WRITE,
+        command,
+        I2C_SMBUS_BLOCK_PROC_CALL,
+        Some(&mut data),
+    )?;
+
+    Ok(block_of(&data))
+}
+
+/// Builds block data with the leading length byte.
+fn block_data(values: &[u8]) -> Result<I2cSmbusData, I2CError> {
+    if values.len() > I2C_SMBUS_BLOCK_MAX {
+        return Err(I2CError::InvalidTransaction);
+    }
+
+    let mut block = [0; I2C_SMBUS_BLOCK_MAX + 2];
+    block[0] = values.len() as u8;
+    block[1..=values.len()].copy_from_slice(values);
+
+    Ok(I2cSmbusData { block })
+}
+
+/// Extracts the bytes of a block received from the device.
+fn block_of(data: &I2cSmbusData) -> Vec<u8> {
+    let block = unsafe { &data.block };
+    let len = (block[0] as usize).min(I2C_SMBUS_BLOCK_MAX);
+
+    block[1..=len].to_vec()
+}
@@ -2,15 +2,20 @@
 
 pub mod platform;
 
+pub mod at;
+pub mod gnss;
 pub mod gpio;
 pub mod i2c;
+pub mod modbus;
 pub mod pwm;
+pub mod register;
 pub mod spi;
+pub mod timing;
 pub mod uart;
 
-use i2c::I2C;
+use i2c::{I2cAddress, I2cBus, I2C};
 use pwm::PwmPin;
-use spi::Spi;
+use spi::{ChipSelect, Spi, SpiBus, SpiConfig, SpiDevice};
 use thiserror::Error;
 use uart::{InvalidUARTConfig, SerialConfig, Uart};
 
@@ -42,8 +47,10 @@ pub struct WiringX {
     platform: Platform,
     gpio_handles: Hand<i32>,
     pwm_handles: Hand<i32>,
-    i2c_handles: Hand<(PathBuf, i32)>,
+    i2c_handles: Hand<(PathBuf, I2cAddress)>,
+    i2c_bus_handles: Hand<PathBuf>,
     spi_handles: Hand<i32>,
+    spi_bus_handles: Hand<PathBuf>,
     uart_handles: Hand<PathBuf>,
 }
 
@@ -64,7 +71,9 @@ impl WiringX {
                 gpio_handles: Mutex::new(HashSet::new()).into(),
                 pwm_handles: Mutex::new(HashSet::new()).into(),
                 i2c_handles: Mutex::new(HashSet::new()).into(),
+                i2c_bus_handles: Mutex::new(HashSet::new()).into(),
                 spi_handles: Mutex::new(HashSet::new()).into(),
+                spi_bus_handles: Mutex::new(HashSet::new()).into(),
                 uart_handles: Mutex::new(HashSet::new()).into(),
             }
         });
@@ -133,17 +142,81 @@ impl WiringX {
     }
 
     /// Sets up an I2C instance for the given I2C device path, for example `/dev/i2c-1`, and device address.
-    pub fn setup_i2c(&self, dev: PathBuf, addr: i32) -> Result<I2C, WiringXError> {
+    ///
+    /// Fails if the bus is opened with `setup_i2c_bus` already, since transfers of this instance would bypass its lock.
+    pub fn setup_i2c(&self, dev: PathBuf, addr: I2cAddress) -> Result<I2C, WiringXError> {
+        if self.i2c_bus_handles.lock().contains(&dev) {
+            return Err(WiringXError::PinUsed);
+        }
+
         I2C::new(dev, addr, self.i2c_handles.clone())
     }
 
+    /// Opens the given I2C bus, for example `/dev/i2c-1`, to share it between multiple devices.
+    ///
+    /// Unlike `setup_i2c` all devices on the bus share a single file descriptor and are accessed under a lock.
+    /// Fails if any device on the bus is opened with `setup_i2c` already.
+    pub fn setup_i2c_bus(&self, dev: PathBuf) -> Result<I2cBus, WiringXError> {
+        if self.i2c_handles.lock().iter().any(|(path, _)| *path == dev) {
+            return Err(WiringXError::PinUsed);
+        }
+
+        I2cBus::new(dev, self.i2c_bus_handles.clone())
+    }
+
     /// Sets up an SPI instance for the given device channel.
     ///
-    /// Speed is measured in Hertz here.
+    /// Speed is measured in Hertz here. Fails if the spidev device of the channel is opened with `setup_spi_bus`
+    /// already, since transfers of this instance would bypass its lock and chip select handling.
     pub fn setup_spi(&self, channel: i32, speed: u32) -> Result<Spi, WiringXError> {
+        let dev = spi::wiringx_spidev_path(channel);
+
+        if self.spi_bus_handles.lock().contains(&dev) {
+            return Err(WiringXError::PinUsed);
+        }
+
         Spi::new(channel, speed as i32, self.spi_handles.clone())
     }
 
+    /// Opens the given spidev device, for example `/dev/spidev0.0`, to share it between multiple devices.
+    ///
+    /// Devices on the bus can use the chip select of the spidev device or any GPIO as chip select.
+    /// Fails if the device is opened with `setup_spi` already.
+    pub fn setup_spi_bus(&self, dev: PathBuf) -> Result<SpiBus, WiringXError> {
+        let used = self
+            .spi_handles
+            .lock()
+            .iter()
+            .any(|&channel| spi::wiringx_spidev_path(channel) == dev);
+
+        if used {
+            return Err(WiringXError::PinUsed);
+        }
+
+        SpiBus::new(dev, self.spi_bus_handles.clone())
+    }
+
+    /// Opens the given spidev device, for example `/dev/spidev1.0`, as a single device using its own chip select.
+    ///
+    /// Unlike `setup_spi` this works with any SPI controller, not only the one wiringX knows for the platform.
+    pub fn setup_spi_device(
+        &self,
+        dev: PathBuf,
+        config: SpiConfig,
+    ) -> Result<SpiDevice, WiringXError> {
+        self.setup_spi_bus(dev)?.device(ChipSelect::Native, config)
+    }
+
+    /// Opens the spidev device of the given SPI controller and chip select, like `setup_spi_device`.
+    pub fn setup_spi_device_at(
+        &self,
+        bus: u32,
+        cs: u32,
+        config: SpiConfig,
+    ) -> Result<SpiDevice, WiringXError> {
+        self.setup_spi_device(spi::spidev_path(bus, cs), config)
+    }
+
     pub fn setup_uart(&self, dev: PathBuf, config: SerialConfig) -> Result<Uart, WiringXError> {
         Uart::new(dev, config, self.uart_handles.clone())
     }
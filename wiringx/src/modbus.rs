@@ -0,0 +1,303 @@
+//! Modbus RTU master and slave over a UART.
+
+mod master;
+mod slave;
+
+pub use master::ModbusMaster;
+pub use slave::{ModbusSlave, RegisterMap};
+
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::{
+    timing::sleep_until,
+    uart::{Parity, SerialConfig, StopBits, Uart},
+    WiringXError,
+};
+
+/// Unit address every slave executes write requests for, without answering.
+pub const BROADCAST: u8 = 0;
+
+/// Function codes of the supported requests.
+pub(crate) const READ_COILS: u8 = 0x01;
+pub(crate) const READ_DISCRETE_INPUTS: u8 = 0x02;
+pub(crate) const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub(crate) const READ_INPUT_REGISTERS: u8 = 0x04;
+pub(crate) const WRITE_SINGLE_COIL: u8 = 0x05;
+pub(crate) const WRITE_SINGLE_REGISTER: u8 = 0x06;
+pub(crate) const WRITE_MULTIPLE_COILS: u8 = 0x0f;
+pub(crate) const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// Bit of the function code marking an exception response.
+pub(crate) const EXCEPTION_BIT: u8 = 0x80;
+
+/// Value of a coil turned on in a write single coil request.
+pub(crate) const COIL_ON: u16 = 0xff00;
+
+/// Maximum number of coils or discrete inputs read at once.
+pub(crate) const MAX_READ_BITS: u16 = 2000;
+/// Maximum number of registers read at once.
+pub(crate) const MAX_READ_REGISTERS: u16 = 125;
+/// Maximum number of coils written at once.
+pub(crate) const MAX_WRITE_COILS: u16 = 1968;
+/// Maximum number of registers written at once.
+pub(crate) const MAX_WRITE_REGISTERS: u16 = 123;
+
+/// Shortest silence detected between frames.
+///
+/// The kernel hands received bytes over in chunks, so shorter gaps can not be observed reliably.
+const MIN_SILENCE: Duration = Duration::from_millis(5);
+
+/// Exception code of a Modbus exception response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExceptionCode {
+    /// The function is not supported.
+    IllegalFunction,
+    /// The address or address range does not exist.
+    IllegalDataAddress,
+    /// A value of the request is not allowed.
+    IllegalDataValue,
+    /// The slave failed to execute the request.
+    ServerDeviceFailure,
+    /// The request was accepted but takes long to execute.
+    Acknowledge,
+    /// The slave is busy with a previous request.
+    ServerDeviceBusy,
+    /// Any other exception code.
+    Other(u8),
+}
+
+impl ExceptionCode {
+    /// Returns the exception code of the given number.
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x01 => Self::IllegalFunction,
+            0x02 => Self::IllegalDataAddress,
+            0x03 => Self::IllegalDataValue,
+            0x04 => Self::ServerDeviceFailure,
+            0x05 => Self::Acknowledge,
+            0x06 => Self::ServerDeviceBusy,
+            code => Self::Other(code),
+        }
+    }
+
+    /// Returns the number of this exception code.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::IllegalFunction => 0x01,
+            Self::IllegalDataAddress => 0x02,
+            Self::IllegalDataValue => 0x03,
+            Self::ServerDeviceFailure => 0x04,
+            Self::Acknowledge => 0x05,
+            Self::ServerDeviceBusy => 0x06,
+            Self::Other(code) => *code,
+        }
+    }
+}
+
+/// Errors of Modbus transfers.
+#[derive(Debug, Error)]
+pub enum ModbusError {
+    /// The slave did not answer in time.
+    #[error("The Modbus slave did not respond in time.")]
+    Timeout,
+    /// A frame was received with a wrong CRC.
+    #[error("Received a Modbus frame with an invalid CRC.")]
+    Crc,
+    /// A frame did not match the request or the protocol.
+    #[error("Received an invalid Modbus frame.")]
+    InvalidFrame,
+    /// The request can not be sent, for example because of too many values or an invalid unit address.
+    #[error("The Modbus request is not valid.")]
+    InvalidRequest,
+    /// The slave answered with an exception.
+    #[error("The Modbus slave responded with exception {0:?}.")]
+    Exception(ExceptionCode),
+    /// The UART failed.
+    #[error("UART error: {0}")]
+    Uart(WiringXError),
+}
+
+impl From<WiringXError> for ModbusError {
+    fn from(error: WiringXError) -> Self {
+        Self::Uart(error)
+    }
+}
+
+/// Calculates the Modbus CRC-16 of the bytes, sent least significant byte first.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u16, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Appends the CRC to the frame.
+pub(crate) fn append_crc(frame: &mut Vec<u8>) {
+    let crc = crc16(frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// Checks and removes the CRC at the end of the frame.
+pub(crate) fn strip_crc(frame: &[u8]) -> Result<&[u8], ModbusError> {
+    let Some((data, crc)) = frame.split_last_chunk::<2>() else {
+        return Err(ModbusError::InvalidFrame);
+    };
+
+    if crc16(data) != u16::from_le_bytes(*crc) {
+        return Err(ModbusError::Crc);
+    }
+
+    Ok(data)
+}
+
+/// Packs the bits into bytes, least significant bit first.
+pub(crate) fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (index, &bit)| byte | ((bit as u8) << index))
+        })
+        .collect()
+}
+
+/// Unpacks the given number of bits from bytes, least significant bit first.
+pub(crate) fn unpack_bits(bytes: &[u8], count: usize) -> Vec<bool> {
+    (0..count)
+        .map(|index| bytes[index / 8] & (1 << (index % 8)) != 0)
+        .collect()
+}
+
+/// Splits the bytes into big endian registers.
+pub(crate) fn registers(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|register| u16::from_be_bytes([register[0], register[1]]))
+        .collect()
+}
+
+/// Timing of RTU frames at a baud rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FrameTiming {
+    /// Silence of 3.5 characters separating frames.
+    pub(crate) t3_5: Duration,
+}
+
+impl FrameTiming {
+    /// Derives the timing from the framing of the UART.
+    ///
+    /// Above 19200 baud the specification fixes the frame gap at 1.75 ms.
+    pub(crate) fn new(config: &SerialConfig) -> Self {
+        if config.baud_rate > 19200 {
+            return Self {
+                t3_5: Duration::from_micros(1750),
+            };
+        }
+
+        let parity_bits = match config.parity {
+            Parity::None => 0,
+            _ => 1,
+        };
+        let stop_bits = match config.stop_bits {
+            StopBits::One => 2,
+            StopBits::OneAndHalf => 3,
+            StopBits::Two => 4,
+        };
+
+        // Bits of a character in half bits, from the start bit to the stop bits.
+        let half_bits = 2 * (1 + config.data_bits + parity_bits) + stop_bits;
+        let character = Duration::from_secs(1) * half_bits / (2 * config.baud_rate);
+
+        Self {
+            t3_5: character * 7 / 2,
+        }
+    }
+
+    /// Silence after which a frame counts as complete.
+    pub(crate) fn silence(&self) -> Duration {
+        self.t3_5.max(MIN_SILENCE)
+    }
+}
+
+/// RTU framing on a UART, keeping track of the bus activity to respect the frame gap.
+#[derive(Debug)]
+pub(crate) struct Link {
+    pub(crate) uart: Uart,
+    pub(crate) timing: FrameTiming,
+    /// End of the last frame sent or received.
+    last_activity: Instant,
+}
+
+impl Link {
+    pub(crate) fn new(uart: Uart) -> Result<Self, WiringXError> {
+        let timing = FrameTiming::new(&uart.config()?);
+
+        Ok(Self {
+            uart,
+            timing,
+            last_activity: Instant::now(),
+        })
+    }
+
+    /// Sends the frame with its CRC after waiting for the frame gap.
+    pub(crate) fn send(&mut self, mut frame: Vec<u8>) -> Result<(), ModbusError> {
+        append_crc(&mut frame);
+
+        sleep_until(self.last_activity + self.timing.t3_5);
+
+        self.uart.write(&frame)?;
+        self.uart.drain()?;
+        self.last_activity = Instant::now();
+
+        Ok(())
+    }
+
+    /// Reads exactly as many bytes as fit into the buffer.
+    ///
+    /// The first byte has to arrive within the timeout, the following ones without a frame gap in between.
+    pub(crate) fn receive_exact(
+        &mut self,
+        buffer: &mut [u8],
+        timeout: Duration,
+    ) -> Result<(), ModbusError> {
+        let mut received = 0;
+
+        while received < buffer.len() {
+            let timeout = if received == 0 {
+                timeout
+            } else {
+                self.timing.silence()
+            };
+
+            let read = self.uart.read(&mut buffer[received..], timeout)?;
+
+            if read == 0 {
+                return Err(ModbusError::Timeout);
+            }
+
+            received += read;
+            self.last_activity = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Discards everything received until the bus was silent for a frame gap.
+    pub(crate) fn resync(&mut self) -> Result<(), ModbusError> {
+        let mut buffer = [0; 256];
+
+        while self.uart.read(&mut buffer, self.timing.silence())? > 0 {
+            self.last_activity = Instant::now();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,559 @@
+//! Modbus RTU master, sending requests to slaves.
+
+use std::time::Duration;
+
+use crate::uart::Uart;
+
+use super::{
+    pack_bits, registers, strip_crc, unpack_bits, ExceptionCode, Link, ModbusError, BROADCAST,
+    COIL_ON, EXCEPTION_BIT, MAX_READ_BITS, MAX_READ_REGISTERS, MAX_WRITE_COILS,
+    MAX_WRITE_REGISTERS, READ_COILS, READ_DISCRETE_INPUTS, READ_HOLDING_REGISTERS,
+    READ_INPUT_REGISTERS, WRITE_MULTIPLE_COILS, WRITE_MULTIPLE_REGISTERS, WRITE_SINGLE_COIL,
+    WRITE_SINGLE_REGISTER,
+};
+
+/// Time a slave gets to start its response by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Modbus RTU master on a UART.
+///
+/// The frame gap of 3.5 characters gets derived from the configuration of the UART when the master is created.
+/// Requests to the unit address `BROADCAST` are only possible for writes and get no response.
+#[derive(Debug)]
+pub struct ModbusMaster {
+    link: Link,
+    timeout: Duration,
+}
+
+impl ModbusMaster {
+    /// Creates a master on the UART, which has to be configured for the bus already.
+    pub fn new(uart: Uart) -> Result<Self, ModbusError> {
+        Ok(Self {
+            link: Link::new(uart)?,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sets how long slaves get to start their response, one second by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the UART of this master.
+    pub fn uart(&self) -> &Uart {
+        &self.link.uart
+    }
+
+    /// Returns the UART, consuming the master.
+    pub fn into_inner(self) -> Uart {
+        self.link.uart
+    }
+
+    /// Reads up to 2000 coils starting at the address.
+    pub fn read_coils(
+        &mut self,
+        unit: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<bool>, ModbusError> {
+        self.read_bits(unit, READ_COILS, address, count)
+    }
+
+    /// Reads up to 2000 discrete inputs starting at the address.
+    pub fn read_discrete_inputs(
+        &mut self,
+        unit: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<bool>, ModbusError> {
+        self.read_bits(unit, READ_DISCRETE_INPUTS, address, count)
+    }
+
+    /// Reads up to 125 holding registers starting at the address.
+    pub fn read_holding_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ModbusError> {
+        self.read_registers(unit, READ_HOLDING_REGISTERS, address, count)
+    }
+
+    /// Reads up to 125 input registers starting at the address.
+    pub fn read_input_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ModbusError> {
+        self.read_registers(unit, READ_INPUT_REGISTERS, address, count)
+    }
+
+    /// Turns the coil at the address on or off.
+    pub fn write_single_coil(
+        &mut self,
+        unit: u8,
+        address: u16,
+        value: bool,
+    ) -> Result<(), ModbusError> {
+        let value = if value { COIL_ON } else { 0 };
+
+        self.write(unit, WRITE_SINGLE_COIL, address, value, &[])
+    }
+
+    /// Writes the register at the address.
+    pub fn write_single_register(
+        &mut self,
+        unit: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<(), ModbusError> {
+        self.write(unit, WRITE_SINGLE_REGISTER, address, value, &[])
+    }
+
+    /// Writes up to 1968 coils starting at the address.
+    pub fn write_multiple_coils(
+        &mut self,
+        unit: u8,
+        address: u16,
+        values: &[bool],
+    ) -> Result<(), ModbusError> {
+        let count = check_count(values.len(), MAX_WRITE_COILS)?;
+
+        self.write(
+            unit,
+            WRITE_MULTIPLE_COILS,
+            address,
+            count,
+            &pack_bits(values),
+        )
+    }
+
+    /// Writes up to 123 registers starting at the address.
+    pub fn write_multiple_registers(
+        &mut self,
+        unit: u8,
+        address: u16,
+        values: &[u16],
+    ) -> Result<(), ModbusError> {
+        let count = check_count(values.len(), MAX_WRITE_REGISTERS)?;
+        let data: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+
+        self.write(unit, WRITE_MULTIPLE_REGISTERS, address, count, &data)
+    }
+
+    fn read_bits(
+        &mut self,
+        unit: u8,
+        function: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<bool>, ModbusError> {
+        check_count(count as usize, MAX_READ_BITS)?;
+
+        let data = self.read(unit, function, address, count)?;
+
+        if data.len() != (count as usize).div_ceil(8) {
+            return Err(ModbusError::InvalidFrame);
+        }
+
+        Ok(unpack_bits(&data, count as usize))
+    }
+
+    fn read_registers(
+        &mut self,
+        unit: u8,
+        function: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ModbusError> {
+        check_count(count as usize, MAX_READ_REGISTERS)?;
+
+        let data = self.read(unit, function, address, count)?;
+
+        if data.len() != count as usize * 2 {
+            return Err(ModbusError::InvalidFrame);
+        }
+
+        Ok(registers(&data))
+    }
+
+    /// Sends a read request and returns the data bytes of the response.
+    fn read(
+        &mut self,
+        unit: u8,
+        function: u8,
+        address: u16,
+        count: u16,
+    ) -> Result<Vec<u8>, ModbusError> {
+        if unit == BROADCAST {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        let mut request = vec![unit, function];
+        request.extend_from_slice(&address.to_be_bytes());
+        request.extend_from_slice(&count.to_be_bytes());
+
+        let response = self.transact(request)?;
+
+        // Unit, function and byte count precede the data.
+        match response.get(2) {
+            Some(&len) if response.len() == 3 + len as usize => Ok(response[3..].to_vec()),
+            _ => Err(ModbusError::InvalidFrame),
+        }
+    }
+
+    /// Sends a write request and checks the echoed address and value or count.
+    fn write(
+        &mut self,
+        unit: u8,
+        function: u8,
+        address: u16,
+        value: u16,
+        data: &[u8],
+    ) -> Result<(), ModbusError> {
+        let mut request = vec![unit, function];
+        request.extend_from_slice(&address.to_be_bytes());
+        request.extend_from_slice(&value.to_be_bytes());
+
+        if !data.is_empty() {
+            request.push(data.len() as u8);
+            request.extend_from_slice(data);
+        }
+
+        if unit == BROADCAST {
+            return self.link.send(request);
+        }
+
+        let echo = request[..6].to_vec();
+        let response = self.transact(request)?;
+
+        if response != echo {
+            return Err(ModbusError::InvalidFrame);
+        }
+
+        Ok(())
+    }
+
+    /// Sends the request and returns the response without its CRC.
+    fn transact(&mut self, request: Vec<u8>) -> Result<Vec<u8>, ModbusError> {
+        let (unit, function) = (request[0], request[1]);
+
+        self.link.send(request)?;
+
+        let result = self.receive(unit, function);
+
+        // Leftovers of a broken or cut off response would corrupt the next one.
+        if matches!(
+            result,
+            Err(ModbusError::Timeout | ModbusError::Crc | ModbusError::InvalidFrame)
+        ) {
+            self.link.resync()?;
+        }
+
+        result
+    }
+
+    fn receive(&mut self, unit: u8, function: u8) -> Result<Vec<u8>, ModbusError> {
+        let mut frame = vec![0; 3];
+        self.link.receive_exact(&mut frame, self.timeout)?;
+
+        if frame[0] != unit || frame[1] & !EXCEPTION_BIT != function {
+            return Err(ModbusError::InvalidFrame);
+        }
+
+        // Exceptions carry one code byte, reads a byte count and writes echo address and value.
+        let remaining = if frame[1] & EXCEPTION_BIT != 0 {
+            2
+        } else {
+            match function {
+                READ_COILS
+                | READ_DISCRETE_INPUTS
+                | READ_HOLDING_REGISTERS
+                | READ_INPUT_REGISTERS => frame[2] as usize + 2,
+                _ => 5,
+            }
+        };
+
+        frame.resize(3 + remaining, 0);
+        self.link
+            .receive_exact(&mut frame[3..], self.link.timing.silence())?;
+
+        let response = strip_crc(&frame)?;
+
+        if response[1] & EXCEPTION_BIT != 0 {
+            return Err(ModbusError::Exception(ExceptionCode::from_code(
+                response[2],
+            )));
+        }
+
+        Ok(response.to_vec())
+    }
+}
+
+/// Checks that between one and the maximum number of values get transferred.
+fn check_count(count: usize, max: u16) -> Result<u16, ModbusError> {
+    if count == 0 || count > max as usize {
+        return Err(ModbusError::InvalidRequest);
+    }
+
+    Ok(count as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::{
+        modbus::{append_crc, ModbusSlave, RegisterMap},
+        uart::{pty_pair, FlowControl, Parity, SerialConfig, StopBits},
+    };
+
+    const CONFIG: SerialConfig = SerialConfig {
+        baud_rate: 115200,
+        data_bits: 8,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+        flow_control: FlowControl::None,
+    };
+
+    /// Slave data model with 16 coils and 16 registers, discrete inputs and input registers mirroring them.
+    #[derive(Debug, Default)]
+    struct Memory {
+        coils: [bool; 16],
+        registers: [u16; 16],
+    }
+
+    impl Memory {
+        fn range(address: u16, count: usize) -> Result<std::ops::Range<usize>, ExceptionCode> {
+            let start = address as usize;
+
+            (start + count <= 16)
+                .then_some(start..start + count)
+                .ok_or(ExceptionCode::IllegalDataAddress)
+        }
+    }
+
+    impl RegisterMap for Memory {
+        fn read_coils(&mut self, address: u16, count: u16) -> Result<Vec<bool>, ExceptionCode> {
+            Ok(self.coils[Self::range(address, count as usize)?].to_vec())
+        }
+
+        fn read_discrete_inputs(
+            &mut self,
+            address: u16,
+            count: u16,
+        ) -> Result<Vec<bool>, ExceptionCode> {
+            Ok(self.coils[Self::range(address, count as usize)?]
+                .iter()
+                .map(|coil| !coil)
+                .collect())
+        }
+
+        fn read_holding_registers(
+            &mut self,
+            address: u16,
+            count: u16,
+        ) -> Result<Vec<u16>, ExceptionCode> {
+            Ok(self.registers[Self::range(address, count as usize)?].to_vec())
+        }
+
+        fn read_input_registers(
+            &mut self,
+            address: u16,
+            count: u16,
+        ) -> Result<Vec<u16>, ExceptionCode> {
+            Ok(self.registers[Self::range(address, count as usize)?]
+                .iter()
+                .map(|register| register + 1)
+                .collect())
+        }
+
+        fn write_coils(&mut self, address: u16, values: &[bool]) -> Result<(), ExceptionCode> {
+            self.coils[Self::range(address, values.len())?].copy_from_slice(values);
+            Ok(())
+        }
+
+        fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<(), ExceptionCode> {
+            self.registers[Self::range(address, values.len())?].copy_from_slice(values);
+            Ok(())
+        }
+    }
+
+    /// Runs the test against a slave with unit address 1 on the other side of a pseudo terminal.
+    fn with_slave(test: impl FnOnce(&mut ModbusMaster)) -> Memory {
+        let (master, slave) = pty_pair(CONFIG);
+        let mut master = ModbusMaster::new(master).unwrap();
+        let mut slave = ModbusSlave::new(slave, 1).unwrap();
+        master.set_timeout(Duration::from_millis(200));
+
+        let done = AtomicBool::new(false);
+        let mut memory = Memory::default();
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let _ = slave.poll(&mut memory, Duration::from_millis(20));
+                }
+            });
+
+            test(&mut master);
+            done.store(true, Ordering::Relaxed);
+        });
+
+        memory
+    }
+
+    /// Reads a request of the given length from the peer side.
+    fn read_request(uart: &Uart, len: usize) -> Vec<u8> {
+        let mut request = vec![0; len];
+        let mut received = 0;
+
+        while received < len {
+            received += uart
+                .read(&mut request[received..], Duration::from_secs(1))
+                .unwrap();
+        }
+
+        request
+    }
+
+    #[test]
+    fn round_trips() {
+        let memory = with_slave(|master| {
+            master.write_single_coil(1, 3, true).unwrap();
+            master
+                .write_multiple_coils(1, 8, &[true, false, true])
+                .unwrap();
+            assert_eq!(
+                master.read_coils(1, 2, 9).unwrap(),
+                [false, true, false, false, false, false, true, false, true]
+            );
+            assert_eq!(master.read_discrete_inputs(1, 3, 2).unwrap(), [false, true]);
+
+            master.write_single_register(1, 0, 0x1234).unwrap();
+            master
+                .write_multiple_registers(1, 14, &[0xabcd, 0xffff])
+                .unwrap();
+            assert_eq!(
+                master.read_holding_registers(1, 14, 2).unwrap(),
+                [0xabcd, 0xffff]
+            );
+            assert_eq!(master.read_input_registers(1, 0, 2).unwrap(), [0x1235, 1]);
+        });
+
+        assert_eq!(memory.registers[0], 0x1234);
+        assert!(memory.coils[3] && memory.coils[8] && memory.coils[10]);
+    }
+
+    #[test]
+    fn exceptions() {
+        with_slave(|master| {
+            assert!(matches!(
+                master.read_holding_registers(1, 15, 2),
+                Err(ModbusError::Exception(ExceptionCode::IllegalDataAddress))
+            ));
+            assert!(matches!(
+                master.write_multiple_coils(1, 15, &[true; 2]),
+                Err(ModbusError::Exception(ExceptionCode::IllegalDataAddress))
+            ));
+
+            // The exception does not disturb the following request.
+            assert_eq!(master.read_coils(1, 15, 1).unwrap(), [false]);
+        });
+    }
+
+    #[test]
+    fn broadcast() {
+        let memory = with_slave(|master| {
+            let start = Instant::now();
+            master.write_single_register(BROADCAST, 5, 42).unwrap();
+            assert!(start.elapsed() < Duration::from_millis(100));
+
+            assert!(matches!(
+                master.read_holding_registers(BROADCAST, 5, 1),
+                Err(ModbusError::InvalidRequest)
+            ));
+            assert_eq!(master.read_holding_registers(1, 5, 1).unwrap(), [42]);
+        });
+
+        assert_eq!(memory.registers[5], 42);
+    }
+
+    #[test]
+    fn other_unit_times_out() {
+        with_slave(|master| {
+            assert!(matches!(
+                master.read_coils(2, 0, 1),
+                Err(ModbusError::Timeout)
+            ));
+            assert_eq!(master.read_coils(1, 0, 1).unwrap(), [false]);
+        });
+    }
+
+    #[test]
+    fn crc_error_resyncs() {
+        let (master, peer) = pty_pair(CONFIG);
+        let mut master = ModbusMaster::new(master).unwrap();
+        master.set_timeout(Duration::from_millis(200));
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                read_request(&peer, 8);
+                peer.write(&[1, 3, 2, 0, 7, 0, 0]).unwrap();
+
+                read_request(&peer, 8);
+                let mut response = vec![1, 3, 2, 0, 9];
+                append_crc(&mut response);
+                peer.write(&response).unwrap();
+            });
+
+            assert!(matches!(
+                master.read_holding_registers(1, 0, 1),
+                Err(ModbusError::Crc)
+            ));
+            assert_eq!(master.read_holding_registers(1, 0, 1).unwrap(), [9]);
+        });
+    }
+
+    #[test]
+    fn cut_off_response_resyncs() {
+        // At 300 baud the frame gap is 117 ms, long enough to tell the late bytes apart reliably.
+        let (master, peer) = pty_pair(SerialConfig {
+            baud_rate: 300,
+            ..CONFIG
+        });
+        let mut master = ModbusMaster::new(master).unwrap();
+        master.set_timeout(Duration::from_secs(1));
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut response = vec![1, 3, 4, 0, 1, 0, 2];
+                append_crc(&mut response);
+
+                read_request(&peer, 8);
+                peer.write(&response[..4]).unwrap();
+                // Arrives after the frame gap, while the master discards the input.
+                thread::sleep(Duration::from_millis(180));
+                peer.write(&response[4..]).unwrap();
+
+                read_request(&peer, 8);
+                peer.write(&response).unwrap();
+            });
+
+            assert!(matches!(
+                master.read_holding_registers(1, 0, 2),
+                Err(ModbusError::Timeout)
+            ));
+            assert_eq!(master.read_holding_registers(1, 0, 2).unwrap(), [1, 2]);
+        });
+    }
+}
@@ -0,0 +1,477 @@
+//! Modbus RTU slave, answering requests of a master.
+
+use std::time::Duration;
+
+use crate::uart::Uart;
+
+use super::{
+    pack_bits, registers, strip_crc, unpack_bits, ExceptionCode, Link, ModbusError, BROADCAST,
+    COIL_ON, EXCEPTION_BIT, MAX_READ_BITS, MAX_READ_REGISTERS, MAX_WRITE_COILS,
+    MAX_WRITE_REGISTERS, READ_COILS, READ_DISCRETE_INPUTS, READ_HOLDING_REGISTERS,
+    READ_INPUT_REGISTERS, WRITE_MULTIPLE_COILS, WRITE_MULTIPLE_REGISTERS, WRITE_SINGLE_COIL,
+    WRITE_SINGLE_REGISTER,
+};
+
+/// How long `ModbusSlave::run` waits for a request at once.
+const RUN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Data model of a Modbus slave.
+///
+/// Every function answers with `IllegalFunction` unless implemented. Reads have to return exactly `count` values.
+pub trait RegisterMap {
+    /// Reads coils starting at the address.
+    fn read_coils(&mut self, _address: u16, _count: u16) -> Result<Vec<bool>, ExceptionCode> {
+        Err(ExceptionCode::IllegalFunction)
+    }
+
+    /// Reads discrete inputs starting at the address.
+    fn read_discrete_inputs(
+        &mut self,
+        _address: u16,
+        _count: u16,
+    ) -> Result<Vec<bool>, ExceptionCode> {
+        Err(ExceptionCode::IllegalFunction)
+    }
+
+    /// Reads holding registers starting at the address.
+    fn read_holding_registers(
+        &mut self,
+        _address: u16,
+        _count: u16,
+    ) -> Result<Vec<u16>, ExceptionCode> {
+        Err(ExceptionCode::IllegalFunction)
+    }
+
+    /// Reads input registers starting at the address.
+    fn read_input_registers(
+        &mut self,
+        _address: u16,
+        _count: u16,
+    ) -> Result<Vec<u16>, ExceptionCode> {
+        Err(ExceptionCode::IllegalFunction)
+    }
+
+    /// Writes coils starting at the address, for single and multiple coil requests.
+    fn write_coils(&mut self, _address: u16, _values: &[bool]) -> Result<(), ExceptionCode> {
+        Err(ExceptionCode::IllegalFunction)
+    }
+
+    /// Writes holding registers starting at the address, for single and multiple register requests.
+    fn write_registers(&mut self, _address: u16, _values: &[u16]) -> Result<(), ExceptionCode> {
+        Err(ExceptionCode::IllegalFunction)
+    }
+}
+
+/// Modbus RTU slave on a UART, answering requests for its unit address.
+///
+/// Write requests to `BROADCAST` get executed without a response, frames for other units get skipped.
+#[derive(Debug)]
+pub struct ModbusSlave {
+    link: Link,
+    unit: u8,
+}
+
+impl ModbusSlave {
+    /// Creates a slave with the unit address, between 1 and 247, on the UART.
+    pub fn new(uart: Uart, unit: u8) -> Result<Self, ModbusError> {
+        if !(1..=247).contains(&unit) {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        Ok(Self {
+            link: Link::new(uart)?,
+            unit,
+        })
+    }
+
+    /// Returns the unit address of this slave.
+    pub fn unit(&self) -> u8 {
+        self.unit
+    }
+
+    /// Returns the UART of this slave.
+    pub fn uart(&self) -> &Uart {
+        &self.link.uart
+    }
+
+    /// Returns the UART, consuming the slave.
+    pub fn into_inner(self) -> Uart {
+        self.link.uart
+    }
+
+    /// Waits up to the timeout for a request and handles it.
+    ///
+    /// Returns whether a request for this slave was handled. Broken frames get discarded and returned as error.
+    pub fn poll<M: RegisterMap + ?Sized>(
+        &mut self,
+        map: &mut M,
+        timeout: Duration,
+    ) -> Result<bool, ModbusError> {
+        let mut header = [0; 2];
+
+        match self.link.receive_exact(&mut header, timeout) {
+            Ok(()) => {}
+            Err(ModbusError::Timeout) => return Ok(false),
+            Err(error) => return Err(error),
+        }
+
+        let [unit, function] = header;
+
+        if unit != self.unit && unit != BROADCAST {
+            self.link.resync()?;
+            return Ok(false);
+        }
+
+        let result = self.handle(map, unit, function);
+
+        if matches!(
+            result,
+            Err(ModbusError::Timeout | ModbusError::Crc | ModbusError::InvalidFrame)
+        ) {
+            self.link.resync()?;
+        }
+
+        result.map(|()| true)
+    }
+
+    /// Handles requests until the UART fails.
+    ///
+    /// Broken frames get skipped, so this only returns on errors of the UART.
+    pub fn run<M: RegisterMap + ?Sized>(&mut self, map: &mut M) -> Result<(), ModbusError> {
+        loop {
+            match self.poll(map, RUN_INTERVAL) {
+                Ok(_) => {}
+                Err(ModbusError::Uart(error)) => return Err(ModbusError::Uart(error)),
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Receives the rest of the request following unit and function and answers it.
+    fn handle<M: RegisterMap + ?Sized>(
+        &mut self,
+        map: &mut M,
+        unit: u8,
+        function: u8,
+    ) -> Result<(), ModbusError> {
+        let silence = self.link.timing.silence();
+        let mut frame = vec![unit, function];
+
+        match function {
+            READ_COILS..=WRITE_SINGLE_REGISTER => {
+                frame.resize(8, 0);
+                self.link.receive_exact(&mut frame[2..], silence)?;
+            }
+            WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS => {
+                frame.resize(7, 0);
+                self.link.receive_exact(&mut frame[2..], silence)?;
+
+                let len = frame[6] as usize;
+                frame.resize(7 + len + 2, 0);
+                self.link.receive_exact(&mut frame[7..], silence)?;
+            }
+            _ => {
+                // The length of unknown requests is not known, so everything until the frame gap belongs to it.
+                let mut buffer = [0; 256];
+                loop {
+                    let read = self.link.uart.read(&mut buffer, silence)?;
+                    if read == 0 {
+                        break;
+                    }
+                    frame.extend_from_slice(&buffer[..read]);
+                }
+            }
+        }
+
+        let request = strip_crc(&frame)?;
+
+        // A CRC may match right after the unit, leaving no function.
+        if request.len() < 2 {
+            return Err(ModbusError::InvalidFrame);
+        }
+
+        let response = execute(map, request);
+
+        if unit == BROADCAST {
+            return Ok(());
+        }
+
+        let response = match response {
+            Ok(response) => response,
+            Err(code) => vec![unit, function | EXCEPTION_BIT, code.code()],
+        };
+
+        self.link.send(response)
+    }
+}
+
+/// Executes the request without CRC on the map and returns the response without CRC.
+fn execute<M: RegisterMap + ?Sized>(map: &mut M, request: &[u8]) -> Result<Vec<u8>, ExceptionCode> {
+    let &[unit, function, ..] = request else {
+        return Err(ExceptionCode::IllegalFunction);
+    };
+
+    let Some(&[address_high, address_low, value_high, value_low]) = request.get(2..6) else {
+        return Err(ExceptionCode::IllegalFunction);
+    };
+    let address = u16::from_be_bytes([address_high, address_low]);
+    let value = u16::from_be_bytes([value_high, value_low]);
+
+    // Unit and function are followed by the byte count and the data in read responses.
+    let read_response = |data: Vec<u8>| {
+        let mut response = vec![unit, function, data.len() as u8];
+        response.extend_from_slice(&data);
+        response
+    };
+
+    match function {
+        READ_COILS | READ_DISCRETE_INPUTS => {
+            check_count(value, MAX_READ_BITS)?;
+
+            let bits = if function == READ_COILS {
+                map.read_coils(address, value)?
+            } else {
+                map.read_discrete_inputs(address, value)?
+            };
+
+            if bits.len() != value as usize {
+                return Err(ExceptionCode::ServerDeviceFailure);
+            }
+
+            Ok(read_response(pack_bits(&bits)))
+        }
+        READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+            check_count(value, MAX_READ_REGISTERS)?;
+
+            let values = if function == READ_HOLDING_REGISTERS {
+                map.read_holding_registers(address, value)?
+            } else {
+                map.read_input_registers(address, value)?
+            };
+
+            if values.len() != value as usize {
+                return Err(ExceptionCode::ServerDeviceFailure);
+            }
+
+            Ok(read_response(
+                values
+                    .iter()
+                    .flat_map(|value| value.to_be_bytes())
+                    .collect(),
+            ))
+        }
+        WRITE_SINGLE_COIL => {
+            let state = match value {
+                COIL_ON => true,
+                0 => false,
+                _ => return Err(ExceptionCode::IllegalDataValue),
+            };
+
+            map.write_coils(address, &[state])?;
+
+            Ok(request.to_vec())
+        }
+        WRITE_SINGLE_REGISTER => {
+            map.write_registers(address, &[value])?;
+
+            Ok(request.to_vec())
+        }
+        WRITE_MULTIPLE_COILS => {
+            check_count(value, MAX_WRITE_COILS)?;
+
+            let data = request.get(7..).unwrap_or_default();
+            if data.len() != (value as usize).div_ceil(8) {
+                return Err(ExceptionCode::IllegalDataValue);
+            }
+
+            map.write_coils(address, &unpack_bits(data, value as usize))?;
+
+            Ok(request[..6].to_vec())
+        }
+        WRITE_MULTIPLE_REGISTERS => {
+            check_count(value, MAX_WRITE_REGISTERS)?;
+
+            let data = request.get(7..).unwrap_or_default();
+            if data.len() != value as usize * 2 {
+                return Err(ExceptionCode::IllegalDataValue);
+            }
+
+            map.write_registers(address, &registers(data))?;
+
+            Ok(request[..6].to_vec())
+        }
+        _ => Err(ExceptionCode::IllegalFunction),
+    }
+}
+
+/// Checks that between one and the maximum number of values are requested.
+fn check_count(count: u16, max: u16) -> Result<(), ExceptionCode> {
+    if count == 0 || count > max {
+        return Err(ExceptionCode::IllegalDataValue);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{
+        modbus::append_crc,
+        uart::{pty_pair, FlowControl, Parity, SerialConfig, StopBits},
+    };
+
+    const CONFIG: SerialConfig = SerialConfig {
+        baud_rate: 115200,
+        data_bits: 8,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+        flow_control: FlowControl::None,
+    };
+
+    /// Slave data model with a single holding register at address 0.
+    #[derive(Debug, Default)]
+    struct Register(u16);
+
+    impl RegisterMap for Register {
+        fn read_holding_registers(
+            &mut self,
+            address: u16,
+            count: u16,
+        ) -> Result<Vec<u16>, ExceptionCode> {
+            match (address, count) {
+                (0, 1) => Ok(vec![self.0]),
+                _ => Err(ExceptionCode::IllegalDataAddress),
+            }
+        }
+
+        fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<(), ExceptionCode> {
+            match (address, values) {
+                (0, &[value]) => {
+                    self.0 = value;
+                    Ok(())
+                }
+                _ => Err(ExceptionCode::IllegalDataAddress),
+            }
+        }
+    }
+
+    fn frame(data: &[u8]) -> Vec<u8> {
+        let mut frame = data.to_vec();
+        append_crc(&mut frame);
+        frame
+    }
+
+    /// Reads everything the slave answers within 100 ms.
+    fn response(uart: &Uart) -> Vec<u8> {
+        let mut response = vec![0; 256];
+        let read = uart
+            .read(&mut response, Duration::from_millis(100))
+            .unwrap();
+        response.truncate(read);
+        response
+    }
+
+    #[test]
+    fn short_requests() {
+        let mut map = Register(0);
+
+        assert_eq!(execute(&mut map, &[1]), Err(ExceptionCode::IllegalFunction));
+        assert_eq!(
+            execute(&mut map, &[1, READ_HOLDING_REGISTERS, 0]),
+            Err(ExceptionCode::IllegalFunction)
+        );
+        assert_eq!(
+            execute(&mut map, &[1, WRITE_MULTIPLE_REGISTERS, 0, 0, 0, 1]),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+    }
+
+    #[test]
+    fn answers_requests() {
+        let (master, slave) = pty_pair(CONFIG);
+        let mut slave = ModbusSlave::new(slave, 1).unwrap();
+        let mut map = Register(0x1234);
+
+        master.write(&frame(&[1, 3, 0, 0, 0, 1])).unwrap();
+        assert!(slave.poll(&mut map, Duration::from_secs(1)).unwrap());
+        assert_eq!(response(&master), frame(&[1, 3, 2, 0x12, 0x34]));
+
+        master.write(&frame(&[1, 3, 0, 1, 0, 1])).unwrap();
+        assert!(slave.poll(&mut map, Duration::from_secs(1)).unwrap());
+        assert_eq!(response(&master), frame(&[1, 0x83, 2]));
+
+        master.write(&frame(&[1, 0x2b, 0x0e, 1, 0])).unwrap();
+        assert!(slave.poll(&mut map, Duration::from_secs(1)).unwrap());
+        assert_eq!(response(&master), frame(&[1, 0xab, 1]));
+    }
+
+    #[test]
+    fn broadcast_and_other_units() {
+        let (master, slave) = pty_pair(CONFIG);
+        let mut slave = ModbusSlave::new(slave, 1).unwrap();
+        let mut map = Register(0);
+
+        master.write(&frame(&[BROADCAST, 6, 0, 0, 0, 7])).unwrap();
+        assert!(slave.poll(&mut map, Duration::from_secs(1)).unwrap());
+        assert_eq!(map.0, 7);
+
+        master.write(&frame(&[2, 6, 0, 0, 0, 8])).unwrap();
+        assert!(!slave.poll(&mut map, Duration::from_secs(1)).unwrap());
+        assert_eq!(map.0, 7);
+
+        assert!(response(&master).is_empty());
+    }
+
+    #[test]
+    fn broken_frames() {
+        let (master, slave) = pty_pair(CONFIG);
+        let mut slave = ModbusSlave::new(slave, 1).unwrap();
+        let mut map = Register(0);
+
+        let mut request = frame(&[1, 6, 0, 0, 0, 7]);
+        request[7] ^= 1;
+        master.write(&request).unwrap();
+        assert!(matches!(
+            slave.poll(&mut map, Duration::from_secs(1)),
+            Err(ModbusError::Crc)
+        ));
+
+        // The CRC of the unit alone matches, leaving no function.
+        master.write(&frame(&[1])).unwrap();
+        assert!(matches!(
+            slave.poll(&mut map, Duration::from_secs(1)),
+            Err(ModbusError::InvalidFrame)
+        ));
+
+        assert!(response(&master).is_empty());
+        assert_eq!(map.0, 0);
+    }
+
+    #[test]
+    fn resyncs_after_frame_gap() {
+        let (master, slave) = pty_pair(CONFIG);
+        let mut slave = ModbusSlave::new(slave, 1).unwrap();
+        let mut map = Register(0);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                // Cut off after the address, the rest of the frame never arrives.
+                master.write(&[1, 6, 0]).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                master.write(&frame(&[1, 6, 0, 0, 0, 9])).unwrap();
+            });
+
+            assert!(matches!(
+                slave.poll(&mut map, Duration::from_secs(1)),
+                Err(ModbusError::Timeout)
+            ));
+            assert!(slave.poll(&mut map, Duration::from_secs(1)).unwrap());
+        });
+
+        assert_eq!(map.0, 9);
+        assert_eq!(response(&master), frame(&[1, 6, 0, 0, 0, 9]));
+    }
+}
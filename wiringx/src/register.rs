@@ -0,0 +1,554 @@
+//! Declarative register maps for drivers of I2C and SPI devices.
+//!
+//! Registers get described with the `register!` macro, which generates a typed value with bitfield accessors and
+//! `read`, `write` and `modify` functions working over any `RegisterInterface`. Registers are addressed with a single
+//! byte, unless `Address16` follows the access rights for devices with 16-bit register addresses.
+//!
+//! ```no_run
+//! use wiringx::register::{Readable, Writable};
+//!
+//! wiringx::register! {
+//!     /// Control register of an accelerometer.
+//!     pub struct CtrlReg1: u8 @ 0x20, rw {
+//!         /// Output data rate.
+//!         odr, set_odr: 7, 4;
+//!         /// Low power mode.
+//!         low_power, set_low_power: 3;
+//!     }
+//! }
+//!
+//! # fn run(device: &wiringx::i2c::I2cDevice) -> Result<(), wiringx::i2c::I2CError> {
+//! CtrlReg1::modify(device, |reg| reg.set_odr(0b0101))?;
+//!
+//! let ctrl = CtrlReg1::read(device)?;
+//! println!("Data rate {}, low power {}", ctrl.odr(), ctrl.low_power());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    i2c::{I2CError, I2cDevice, Operation, I2C},
+    spi::{self, Spi, SpiDevice},
+    WiringXError,
+};
+
+/// Access to the registers of a device.
+pub trait RegisterInterface {
+    /// Error of failed transfers.
+    type Error;
+
+    /// Reads the register at the given address into the buffer.
+    ///
+    /// The address consists of one or two bytes, most significant byte first.
+    fn read_register(&self, address: &[u8], data: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Writes the data to the register at the given address.
+    ///
+    /// The address consists of one or two bytes, most significant byte first.
+    fn write_register(&self, address: &[u8], data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Order of the bytes of registers wider than 8 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// Most significant byte first.
+    #[default]
+    BigEndian,
+    /// Least significant byte first.
+    LittleEndian,
+}
+
+/// Integer types registers can be made of.
+pub trait RegisterValue: Copy {
+    /// Width of the register in bytes.
+    const BYTES: usize;
+
+    /// Assembles the value from the bytes received from the device.
+    fn from_bytes(bytes: &[u8], order: ByteOrder) -> Self;
+
+    /// Splits the value into the bytes sent to the device.
+    fn to_bytes(self, bytes: &mut [u8], order: ByteOrder);
+}
+
+macro_rules! impl_register_value {
+    ($($raw:ty),*) => {
+        $(
+            impl RegisterValue for $raw {
+                const BYTES: usize = std::mem::size_of::<$raw>();
+
+                fn from_bytes(bytes: &[u8], order: ByteOrder) -> Self {
+                    let bytes = bytes.try_into().expect("register buffer has the width of the register");
+
+                    match order {
+                        ByteOrder::BigEndian => <$raw>::from_be_bytes(bytes),
+                        ByteOrder::LittleEndian => <$raw>::from_le_bytes(bytes),
+                    }
+                }
+
+                fn to_bytes(self, bytes: &mut [u8], order: ByteOrder) {
+                    let value = match order {
+                        ByteOrder::BigEndian => self.to_be_bytes(),
+                        ByteOrder::LittleEndian => self.to_le_bytes(),
+                    };
+
+                    bytes.copy_from_slice(&value);
+                }
+            }
+        )*
+    };
+}
+
+impl_register_value!(u8, u16, u32);
+
+/// A register of a device. Implemented by the `register!` macro.
+pub trait Register: Copy {
+    /// Address of the register.
+    const ADDRESS: u16;
+    /// Width of the address on the wire in bytes, 1 or 2.
+    const ADDRESS_BYTES: usize = 1;
+    /// Order of the bytes on the wire.
+    const BYTE_ORDER: ByteOrder = ByteOrder::BigEndian;
+
+    /// Integer type holding the register contents.
+    type Value: RegisterValue;
+
+    /// Creates the register from its raw contents.
+    fn from_value(value: Self::Value) -> Self;
+
+    /// Returns the raw contents of the register.
+    fn value(&self) -> Self::Value;
+}
+
+/// Returns the address bytes of the register as sent to the device.
+fn address_bytes<R: Register>(bytes: &mut [u8; 2]) -> &[u8] {
+    *bytes = R::ADDRESS.to_be_bytes();
+
+    &bytes[2 - R::ADDRESS_BYTES..]
+}
+
+/// A register that can be read from the device.
+pub trait Readable: Register {
+    /// Reads the register from the device.
+    fn read<I: RegisterInterface + ?Sized>(interface: &I) -> Result<Self, I::Error> {
+        let mut bytes = [0; 4];
+        let bytes = &mut bytes[..Self::Value::BYTES];
+
+        interface.read_register(address_bytes::<Self>(&mut [0; 2]), bytes)?;
+
+        Ok(Self::from_value(Self::Value::from_bytes(
+            bytes,
+            Self::BYTE_ORDER,
+        )))
+    }
+}
+
+/// A register that can be written to the device.
+pub trait Writable: Register {
+    /// Writes the register to the device.
+    fn write<I: RegisterInterface + ?Sized>(&self, interface: &I) -> Result<(), I::Error> {
+        let mut bytes = [0; 4];
+        let bytes = &mut bytes[..Self::Value::BYTES];
+
+        self.value().to_bytes(bytes, Self::BYTE_ORDER);
+
+        interface.write_register(address_bytes::<Self>(&mut [0; 2]), bytes)
+    }
+
+    /// Reads the register, changes it with the function and writes it back.
+    ///
+    /// Returns the written register. Other bus users may access the device between the read and the write.
+    fn modify<I: RegisterInterface + ?Sized>(
+        interface: &I,
+        f: impl FnOnce(&mut Self),
+    ) -> Result<Self, I::Error>
+    where
+        Self: Readable,
+    {
+        let mut register = Self::read(interface)?;
+        f(&mut register);
+        register.write(interface)?;
+
+        Ok(register)
+    }
+}
+
+/// Returns the mask of a bitfield from its most to its least significant bit, shifted to bit 0.
+#[doc(hidden)]
+pub const fn field_mask(msb: u32, lsb: u32) -> u32 {
+    assert!(
+        msb >= lsb,
+        "the most significant bit of a field is below its least significant bit"
+    );
+
+    let width = msb - lsb + 1;
+
+    if width >= u32::BITS {
+        u32::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// Defines a register with its address, width, access rights and bitfields.
+///
+/// The register is a newtype around its raw integer value, which can be `u8`, `u16` or `u32`. The access rights are
+/// `ro`, `wo` or `rw`, implementing `Readable`, `Writable` or both. Registers wider than 8 bits are big endian, unless
+/// `LittleEndian` follows the access rights. Addresses are a single byte, unless `Address16` follows the access rights.
+///
+/// Bitfields are given with getter and setter name and their most and least significant bit. Single bit fields are
+/// `bool`s, wider fields use the integer type of the register and get masked on write. Fields not fitting the register
+/// and addresses not fitting their width fail to compile.
+///
+/// ```no_run
+/// wiringx::register! {
+///     /// Calibration value, read only and little endian.
+///     pub struct Calibration: u16 @ 0x88, ro, LittleEndian {}
+/// }
+///
+/// wiringx::register! {
+///     /// Configuration of a device with 16-bit register addresses.
+///     pub struct Config: u8 @ 0x0120, rw, Address16 {}
+/// }
+/// ```
+///
+/// ```compile_fail
+/// wiringx::register! {
+///     /// Bits given from least to most significant.
+///     pub struct Status: u8 @ 0x27, ro {
+///         ready, set_ready: 0, 3;
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! register {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident: $raw:ident @ $address:expr, $access:ident $(, $option:ident)* {
+            $(
+                $(#[$field_meta:meta])*
+                $getter:ident, $setter:ident: $msb:literal $(, $lsb:literal)?;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        $vis struct $name(pub $raw);
+
+        impl $crate::register::Register for $name {
+            const ADDRESS: u16 = $address;
+            $($crate::register!(@option $option);)*
+
+            type Value = $raw;
+
+            fn from_value(value: $raw) -> Self {
+                Self(value)
+            }
+
+            fn value(&self) -> $raw {
+                self.0
+            }
+        }
+
+        const _: () = {
+            use $crate::register::Register;
+
+            assert!(
+                $name::ADDRESS_BYTES == 2 || $name::ADDRESS <= 0xff,
+                "the register address does not fit a single byte",
+            );
+            $($crate::register!(@check $raw, $msb $(, $lsb)?);)*
+        };
+
+        $crate::register!(@access $name, $access);
+
+        #[allow(dead_code)]
+        impl $name {
+            $(
+                $crate::register!(@field $raw, $(#[$field_meta])* $getter, $setter, $msb $(, $lsb)?);
+            )*
+        }
+    };
+
+    (@option BigEndian) => {
+        const BYTE_ORDER: $crate::register::ByteOrder = $crate::register::ByteOrder::BigEndian;
+    };
+    (@option LittleEndian) => {
+        const BYTE_ORDER: $crate::register::ByteOrder = $crate::register::ByteOrder::LittleEndian;
+    };
+    (@option Address16) => {
+        const ADDRESS_BYTES: usize = 2;
+    };
+
+    (@check $raw:ident, $bit:literal) => {
+        assert!($bit < <$raw>::BITS, "the bit of a field is outside of the register");
+    };
+    (@check $raw:ident, $msb:literal, $lsb:literal) => {
+        assert!(
+            $msb >= $lsb && $msb < <$raw>::BITS,
+            "the bits of a field are out of order or outside of the register",
+        );
+    };
+
+    (@access $name:ident, ro) => {
+        impl $crate::register::Readable for $name {}
+    };
+    (@access $name:ident, wo) => {
+        impl $crate::register::Writable for $name {}
+    };
+    (@access $name:ident, rw) => {
+        impl $crate::register::Readable for $name {}
+        impl $crate::register::Writable for $name {}
+    };
+
+    (@field $raw:ident, $(#[$meta:meta])* $getter:ident, $setter:ident, $bit:literal) => {
+        $(#[$meta])*
+        pub fn $getter(&self) -> bool {
+            (self.0 >> $bit) & 1 != 0
+        }
+
+        $(#[$meta])*
+        pub fn $setter(&mut self, value: bool) {
+            self.0 = (self.0 & !(1 << $bit)) | ((value as $raw) << $bit);
+        }
+    };
+    (@field $raw:ident, $(#[$meta:meta])* $getter:ident, $setter:ident, $msb:literal, $lsb:literal) => {
+        $(#[$meta])*
+        pub fn $getter(&self) -> $raw {
+            ((self.0 as u32 >> $lsb) & $crate::register::field_mask($msb, $lsb)) as $raw
+        }
+
+        $(#[$meta])*
+        pub fn $setter(&mut self, value: $raw) {
+            let mask = $crate::register::field_mask($msb, $lsb) << $lsb;
+            self.0 = ((self.0 as u32 & !mask) | (((value as u32) << $lsb) & mask)) as $raw;
+        }
+    };
+}
+
+impl RegisterInterface for I2C {
+    type Error = I2CError;
+
+    fn read_register(&self, address: &[u8], data: &mut [u8]) -> Result<(), I2CError> {
+        self.write_read(address, data)
+    }
+
+    fn write_register(&self, address: &[u8], data: &[u8]) -> Result<(), I2CError> {
+        let mut buffer = Vec::with_capacity(address.len() + data.len());
+        buffer.extend_from_slice(address);
+        buffer.extend_from_slice(data);
+
+        self.transaction(&mut [Operation::Write(&buffer)])
+    }
+}
+
+impl RegisterInterface for I2cDevice {
+    type Error = I2CError;
+
+    fn read_register(&self, address: &[u8], data: &mut [u8]) -> Result<(), I2CError> {
+        self.write_read(address, data)
+    }
+
+    fn write_register(&self, address: &[u8], data: &[u8]) -> Result<(), I2CError> {
+        let mut buffer = Vec::with_capacity(address.len() + data.len());
+        buffer.extend_from_slice(address);
+        buffer.extend_from_slice(data);
+
+        self.transaction(&mut [Operation::Write(&buffer)])
+    }
+}
+
+/// Bit of the first SPI byte marking a register read, as used by most sensors.
+const SPI_READ: u8 = 0x80;
+
+/// Returns the address bytes sent over SPI, with the read bit set in the first byte for reads.
+///
+/// Addresses using the read bit themselves can not be told apart from reads and get rejected.
+fn spi_address(address: &[u8], read: bool) -> Result<Vec<u8>, WiringXError> {
+    let mut address = address.to_vec();
+
+    if let Some(first) = address.first_mut() {
+        if *first & SPI_READ != 0 {
+            return Err(WiringXError::Other(format!(
+                "Register address {address:02x?} collides with the SPI read bit."
+            )));
+        }
+
+        if read {
+            *first |= SPI_READ;
+        }
+    }
+
+    Ok(address)
+}
+
+/// Registers of SPI devices get addressed with the first bytes, the most significant bit being set for reads.
+///
+/// Registers whose first address byte has the most significant bit set are out of reach and return an error.
+impl RegisterInterface for Spi {
+    type Error = WiringXError;
+
+    fn read_register(&self, address: &[u8], data: &mut [u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [
+            spi::Operation::Write(&spi_address(address, true)?),
+            spi::Operation::Read(data),
+        ])
+    }
+
+    fn write_register(&self, address: &[u8], data: &[u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [
+            spi::Operation::Write(&spi_address(address, false)?),
+            spi::Operation::Write(data),
+        ])
+    }
+}
+
+impl RegisterInterface for SpiDevice {
+    type Error = WiringXError;
+
+    fn read_register(&self, address: &[u8], data: &mut [u8]) -> Result<(), WiringXError> {
+        self.write_read(&spi_address(address, true)?, data)
+    }
+
+    fn write_register(&self, address: &[u8], data: &[u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [
+            spi::Operation::Write(&spi_address(address, false)?),
+            spi::Operation::Write(data),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, convert::Infallible};
+
+    use super::*;
+
+    /// Device keeping its registers in memory, keyed by their address bytes.
+    #[derive(Default)]
+    struct Memory {
+        registers: RefCell<HashMap<Vec<u8>, Vec<u8>>>,
+    }
+
+    impl RegisterInterface for Memory {
+        type Error = Infallible;
+
+        fn read_register(&self, address: &[u8], data: &mut [u8]) -> Result<(), Infallible> {
+            let registers = self.registers.borrow();
+            let stored = registers.get(address).map_or(&[][..], Vec::as_slice);
+
+            data.fill(0);
+            data[..stored.len()].copy_from_slice(stored);
+
+            Ok(())
+        }
+
+        fn write_register(&self, address: &[u8], data: &[u8]) -> Result<(), Infallible> {
+            self.registers
+                .borrow_mut()
+                .insert(address.to_vec(), data.to_vec());
+
+            Ok(())
+        }
+    }
+
+    crate::register! {
+        struct Ctrl: u8 @ 0x20, rw {
+            odr, set_odr: 7, 4;
+            low_power, set_low_power: 3;
+            axes, set_axes: 2, 0;
+        }
+    }
+
+    crate::register! {
+        struct Calibration: u16 @ 0x88, ro, LittleEndian {
+            high, set_high: 15, 8;
+        }
+    }
+
+    crate::register! {
+        struct Threshold: u32 @ 0x0120, wo, Address16 {
+            all, set_all: 31, 0;
+        }
+    }
+
+    #[test]
+    fn field_masks() {
+        assert_eq!(field_mask(0, 0), 0b1);
+        assert_eq!(field_mask(7, 4), 0b1111);
+        assert_eq!(field_mask(2, 0), 0b111);
+        assert_eq!(field_mask(15, 8), 0xff);
+        assert_eq!(field_mask(31, 0), u32::MAX);
+        assert_eq!(field_mask(31, 31), 1);
+    }
+
+    #[test]
+    fn fields_get_masked() {
+        let mut ctrl = Ctrl::default();
+
+        ctrl.set_odr(0xff);
+        assert_eq!(ctrl, Ctrl(0xf0));
+        assert_eq!(ctrl.odr(), 0xf);
+
+        ctrl.set_low_power(true);
+        ctrl.set_axes(0b101);
+        assert_eq!(ctrl, Ctrl(0xfd));
+        assert!(ctrl.low_power());
+        assert_eq!(ctrl.axes(), 0b101);
+
+        ctrl.set_odr(0b0101);
+        ctrl.set_low_power(false);
+        assert_eq!(ctrl, Ctrl(0x55));
+    }
+
+    #[test]
+    fn read_write_modify() {
+        let memory = Memory::default();
+
+        Ctrl(0x07).write(&memory).unwrap();
+        assert_eq!(memory.registers.borrow()[&vec![0x20]], [0x07]);
+
+        let ctrl = Ctrl::modify(&memory, |reg| reg.set_odr(0b1001)).unwrap();
+        assert_eq!(ctrl, Ctrl(0x97));
+        assert_eq!(Ctrl::read(&memory).unwrap(), Ctrl(0x97));
+    }
+
+    #[test]
+    fn byte_order() {
+        let memory = Memory::default();
+        memory
+            .registers
+            .borrow_mut()
+            .insert(vec![0x88], vec![0x34, 0x12]);
+
+        let calibration = Calibration::read(&memory).unwrap();
+        assert_eq!(calibration, Calibration(0x1234));
+        assert_eq!(calibration.high(), 0x12);
+    }
+
+    #[test]
+    fn wide_address() {
+        let memory = Memory::default();
+
+        Threshold(0x0102_0304).write(&memory).unwrap();
+        assert_eq!(
+            memory.registers.borrow()[&vec![0x01, 0x20]],
+            [0x01, 0x02, 0x03, 0x04]
+        );
+    }
+
+    #[test]
+    fn spi_read_bit() {
+        assert_eq!(spi_address(&[0x0f], true).unwrap(), [0x0f | SPI_READ]);
+        assert_eq!(spi_address(&[0x0f], false).unwrap(), [0x0f]);
+        assert_eq!(spi_address(&[0x01, 0x20], true).unwrap(), [0x81, 0x20]);
+        assert_eq!(spi_address(&[0x7f, 0xff], false).unwrap(), [0x7f, 0xff]);
+    }
+
+    #[test]
+    fn spi_rejects_high_addresses() {
+        for read in [true, false] {
+            assert!(spi_address(&[0x85], read).is_err());
+            assert!(spi_address(&[0x80, 0x00], read).is_err());
+        }
+    }
+}
@@ -1,18 +1,316 @@
 //! Serial peripheral interface communication related objects.
 
-use std::{ffi::c_uchar, os::fd::RawFd};
+mod bus;
+mod ioctl;
+mod spidev;
 
-use wiringx_sys::{wiringXSPIDataRW, wiringXSPIGetFd, wiringXSPISetup};
+pub use bus::{ChipSelect, SpiBus, SpiDevice};
+pub use spidev::{available_devices, spidev_path, SpidevInfo};
+
+use std::{fs, io, os::fd::RawFd, path::PathBuf, sync::OnceLock};
+
+use wiringx_sys::{wiringXSPIGetFd, wiringXSPISetup};
 
 use crate::{Hand, WiringXError};
 
+use ioctl::{
+    SpiIocTransfer, SPI_3WIRE, SPI_CPHA, SPI_CPOL, SPI_CS_HIGH, SPI_IOC_MAX_TRANSFERS,
+    SPI_LSB_FIRST, SPI_RX_DUAL, SPI_RX_QUAD, SPI_TX_DUAL, SPI_TX_QUAD,
+};
+
+/// Clock polarity and phase of an SPI device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpiMode {
+    /// Clock idles low, data is sampled on the rising edge.
+    #[default]
+    Mode0,
+    /// Clock idles low, data is sampled on the falling edge.
+    Mode1,
+    /// Clock idles high, data is sampled on the falling edge.
+    Mode2,
+    /// Clock idles high, data is sampled on the rising edge.
+    Mode3,
+}
+
+/// Number of data lines used by a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum BusWidth {
+    /// Regular SPI over MOSI and MISO.
+    #[default]
+    Single,
+    /// Two data lines.
+    Dual,
+    /// Four data lines.
+    Quad,
+}
+
+impl BusWidth {
+    /// Returns the number of data lines.
+    pub fn lines(&self) -> u8 {
+        match self {
+            Self::Single => 1,
+            Self::Dual => 2,
+            Self::Quad => 4,
+        }
+    }
+}
+
+/// Transfer settings of an SPI device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiConfig {
+    /// Clock polarity and phase.
+    pub mode: SpiMode,
+    /// Clock speed in Hertz.
+    pub speed: u32,
+    /// Bits per word, usually 8.
+    pub bits_per_word: u8,
+    /// Whether words are sent least significant bit first.
+    pub lsb_first: bool,
+    /// Whether the chip select is active high instead of active low.
+    pub cs_active_high: bool,
+    /// Whether data in and out share a single line, allowing only half duplex operations.
+    pub three_wire: bool,
+    /// Widest bus the device sends with in `Operation::WriteWide`.
+    pub tx_width: BusWidth,
+    /// Widest bus the device receives with in `Operation::ReadWide`.
+    pub rx_width: BusWidth,
+}
+
+impl Default for SpiConfig {
+    fn default() -> Self {
+        Self {
+            mode: SpiMode::Mode0,
+            speed: 1_000_000,
+            bits_per_word: 8,
+            lsb_first: false,
+            cs_active_high: false,
+            three_wire: false,
+            tx_width: BusWidth::Single,
+            rx_width: BusWidth::Single,
+        }
+    }
+}
+
+impl SpiConfig {
+    /// Returns the spidev mode flags of this configuration.
+    pub(crate) fn mode_flags(&self) -> u32 {
+        let mut flags = match self.mode {
+            SpiMode::Mode0 => 0,
+            SpiMode::Mode1 => SPI_CPHA,
+            SpiMode::Mode2 => SPI_CPOL,
+            SpiMode::Mode3 => SPI_CPOL | SPI_CPHA,
+        };
+
+        if self.lsb_first {
+            flags |= SPI_LSB_FIRST;
+        }
+        if self.cs_active_high {
+            flags |= SPI_CS_HIGH;
+        }
+        if self.three_wire {
+            flags |= SPI_3WIRE;
+        }
+
+        flags |= match self.tx_width {
+            BusWidth::Single => 0,
+            BusWidth::Dual => SPI_TX_DUAL,
+            BusWidth::Quad => SPI_TX_QUAD,
+        };
+        flags |= match self.rx_width {
+            BusWidth::Single => 0,
+            BusWidth::Dual => SPI_RX_DUAL,
+            BusWidth::Quad => SPI_RX_QUAD,
+        };
+
+        flags
+    }
+}
+
+/// A single part of an SPI transaction. The chip select stays asserted for all operations of a transaction.
+#[derive(Debug)]
+pub enum Operation<'a> {
+    /// Sends the bytes, discarding the received ones.
+    Write(&'a [u8]),
+    /// Fills the buffer with received bytes, sending zeros.
+    Read(&'a mut [u8]),
+    /// Sends the bytes of the buffer and overwrites them with the received ones.
+    ///
+    /// Not possible with three wire devices.
+    Transfer(&'a mut [u8]),
+    /// Sends the bytes over the given number of data lines, up to the `tx_width` of the device.
+    WriteWide(&'a [u8], BusWidth),
+    /// Fills the buffer with bytes received over the given number of data lines, up to the `rx_width` of the device.
+    ReadWide(&'a mut [u8], BusWidth),
+}
+
+/// Location of the spidev module parameter limiting the size of a single message.
+const BUFSIZ_PATH: &str = "/sys/module/spidev/parameters/bufsiz";
+
+/// Message size limit of spidev when the module parameter can not be read.
+const DEFAULT_BUFSIZ: usize = 4096;
+
+/// Returns the maximum number of bytes spidev sends or receives in a single message.
+///
+/// Read once from the `bufsiz` parameter of the spidev module, defaulting to 4096 bytes. Transactions exceeding it
+/// get split into multiple messages.
+pub fn buffer_size() -> usize {
+    static BUFSIZ: OnceLock<usize> = OnceLock::new();
+
+    *BUFSIZ.get_or_init(|| {
+        fs::read_to_string(BUFSIZ_PATH)
+            .ok()
+            .and_then(|bufsiz| bufsiz.trim().parse().ok())
+            .filter(|&bufsiz| bufsiz > 0)
+            .unwrap_or(DEFAULT_BUFSIZ)
+    })
+}
+
+/// Alignment spidev rounds the length of every transfer up to when checking a message against `bufsiz`.
+///
+/// `ARCH_KMALLOC_MINALIGN` depends on the architecture and kernel configuration, this is the largest one in use.
+const KMALLOC_MINALIGN: usize = 128;
+
+/// Executes the operations on the given spidev file descriptor with the speed and word size.
+///
+/// Operations get chunked and spread over as many messages as needed to stay within `buffer_size`. All messages but
+/// the last one ask the controller to keep the chip select asserted, so the device sees a single transaction.
+/// Buffers are handed to the kernel directly without copying them.
+pub(crate) fn transfer(
+    fd: RawFd,
+    operations: &mut [Operation],
+    speed: u32,
+    bits_per_word: u8,
+) -> io::Result<()> {
+    for mut message in messages(operations, speed, bits_per_word, buffer_size()) {
+        ioctl::message(fd, &mut message)?;
+    }
+
+    Ok(())
+}
+
+/// Splits the operations into messages spidev accepts with the given `bufsiz`.
+///
+/// Like spidev, the sent and received bytes get counted separately, every transfer rounded up to the kernel
+/// alignment.
+fn messages(
+    operations: &mut [Operation],
+    speed: u32,
+    bits_per_word: u8,
+    limit: usize,
+) -> Vec<Vec<SpiIocTransfer>> {
+    let align = |len: usize| len.next_multiple_of(KMALLOC_MINALIGN);
+    // Largest chunk fitting into an empty message.
+    let capacity = (limit / KMALLOC_MINALIGN * KMALLOC_MINALIGN).max(KMALLOC_MINALIGN);
+
+    let mut messages = Vec::new();
+    let mut message: Vec<SpiIocTransfer> = Vec::new();
+    let (mut tx_used, mut rx_used) = (0, 0);
+
+    for operation in operations.iter_mut() {
+        let (tx_buf, rx_buf, len, tx_nbits, rx_nbits) = match operation {
+            Operation::Write(buffer) => (buffer.as_ptr() as u64, 0, buffer.len(), 0, 0),
+            Operation::Read(buffer) => (0, buffer.as_mut_ptr() as u64, buffer.len(), 0, 0),
+            Operation::Transfer(buffer) => {
+                let pointer = buffer.as_mut_ptr() as u64;
+                (pointer, pointer, buffer.len(), 0, 0)
+            }
+            Operation::WriteWide(buffer, width) => {
+                (buffer.as_ptr() as u64, 0, buffer.len(), width.lines(), 0)
+            }
+            Operation::ReadWide(buffer, width) => (
+                0,
+                buffer.as_mut_ptr() as u64,
+                buffer.len(),
+                0,
+                width.lines(),
+            ),
+        };
+
+        let mut offset = 0;
+        while offset < len {
+            // Room left in the message for the directions this operation uses.
+            let room = |pointer: u64, used: usize| {
+                if pointer == 0 {
+                    usize::MAX
+                } else {
+                    capacity.saturating_sub(used)
+                }
+            };
+            let mut free = room(tx_buf, tx_used).min(room(rx_buf, rx_used));
+
+            if free == 0 || message.len() == SPI_IOC_MAX_TRANSFERS {
+                messages.push(std::mem::take(&mut message));
+                (tx_used, rx_used) = (0, 0);
+                free = capacity;
+            }
+
+            let chunk = (len - offset).min(free);
+            let at = |pointer: u64| {
+                if pointer == 0 {
+                    0
+                } else {
+                    pointer + offset as u64
+                }
+            };
+
+            message.push(SpiIocTransfer {
+                tx_buf: at(tx_buf),
+                rx_buf: at(rx_buf),
+                len: chunk as u32,
+                speed_hz: speed,
+                bits_per_word,
+                tx_nbits,
+                rx_nbits,
+                ..Default::default()
+            });
+
+            if tx_buf != 0 {
+                tx_used += align(chunk);
+            }
+            if rx_buf != 0 {
+                rx_used += align(chunk);
+            }
+            offset += chunk;
+        }
+    }
+
+    if !message.is_empty() {
+        messages.push(message);
+    }
+
+    let count = messages.len();
+    for message in &mut messages[..count.saturating_sub(1)] {
+        if let Some(last) = message.last_mut() {
+            last.cs_change = 1;
+        }
+    }
+
+    messages
+}
+
+/// Turns a failed spidev ioctl into an error, reporting rejected modes and transfer widths as unsupported.
+pub(crate) fn spi_error(error: io::Error) -> WiringXError {
+    if error.raw_os_error() == Some(libc::EINVAL) {
+        WiringXError::Unsupported
+    } else {
+        WiringXError::Io(error)
+    }
+}
+
 /// A SPI instance.
 #[derive(Debug)]
 pub struct Spi {
     channel: i32,
+    speed: u32,
     handle: Hand<i32>,
 }
 
+/// Returns the spidev device wiringX opens for a channel of `WiringX::setup_spi`.
+pub(crate) fn wiringx_spidev_path(channel: i32) -> PathBuf {
+    // wiringX only knows the first controller and takes the lowest bit of the channel as chip select.
+    spidev_path(0, (channel & 1) as u32)
+}
+
 impl Spi {
     pub(super) fn new(channel: i32, speed: i32, handle: Hand<i32>) -> Result<Self, WiringXError> {
         if handle.lock().contains(&channel) {
@@ -27,7 +325,11 @@ impl Spi {
 
         handle.lock().insert(channel);
 
-        Ok(Self { channel, handle })
+        Ok(Self {
+            channel,
+            speed: speed as u32,
+            handle,
+        })
     }
 
     /// Returns the raw file descriptor of this spi instance.
@@ -36,19 +338,28 @@ impl Spi {
     }
 
     /// Writes the data to the SPI device and overwrites the provided data with the read data from the device.
+    ///
+    /// Data of any size can be transferred, see `transaction`.
     pub fn read_write(&self, data: &mut [u8]) -> Result<(), WiringXError> {
-        let len = data.len();
-        let result = unsafe {
-            wiringXSPIDataRW(self.channel, data.as_mut_ptr() as *mut c_uchar, len as i32)
-        };
+        self.transaction(&mut [Operation::Transfer(data)])
+    }
 
-        if result < 0 {
-            Err(WiringXError::Other(
-                "Failed to read and write to SPI device.".to_string(),
-            ))
-        } else {
-            Ok(())
-        }
+    /// Writes the data to the SPI device, discarding the received data.
+    pub fn write(&self, data: &[u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [Operation::Write(data)])
+    }
+
+    /// Fills the buffer with data read from the SPI device.
+    pub fn read(&self, data: &mut [u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [Operation::Read(data)])
+    }
+
+    /// Executes all operations as one transaction.
+    ///
+    /// Transactions larger than `buffer_size` get split into multiple messages, keeping the chip select asserted in
+    /// between if the controller supports it.
+    pub fn transaction(&self, operations: &mut [Operation]) -> Result<(), WiringXError> {
+        transfer(self.get_fd(), operations, self.speed, 8).map_err(spi_error)
     }
 }
 
@@ -57,3 +368,119 @@ impl Drop for Spi {
         self.handle.lock().remove(&self.channel);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the lengths of the transfers of every message.
+    fn lengths(messages: &[Vec<SpiIocTransfer>]) -> Vec<Vec<u32>> {
+        messages
+            .iter()
+            .map(|message| message.iter().map(|transfer| transfer.len).collect())
+            .collect()
+    }
+
+    /// Returns whether the chip select gets released between messages only.
+    fn cs_change_between_messages(messages: &[Vec<SpiIocTransfer>]) -> bool {
+        messages.iter().enumerate().all(|(index, message)| {
+            message.iter().enumerate().all(|(position, transfer)| {
+                let between = index + 1 < messages.len() && position + 1 == message.len();
+                transfer.cs_change == between as u8
+            })
+        })
+    }
+
+    #[test]
+    fn counts_aligned_lengths() {
+        let command = [0x2c];
+        let framebuffer = vec![0; 4095];
+
+        let messages = messages(
+            &mut [Operation::Write(&command), Operation::Write(&framebuffer)],
+            1_000_000,
+            8,
+            4096,
+        );
+
+        // The command byte takes up a whole alignment unit of the buffer.
+        assert_eq!(lengths(&messages), [vec![1, 3968], vec![127]]);
+        assert!(cs_change_between_messages(&messages));
+
+        let framebuffer = framebuffer.as_ptr() as u64;
+        assert_eq!(messages[0][1].tx_buf, framebuffer);
+        assert_eq!(messages[1][0].tx_buf, framebuffer + 3968);
+        assert_eq!(messages[1][0].rx_buf, 0);
+    }
+
+    #[test]
+    fn counts_directions_separately() {
+        let command = vec![0; 4096];
+        let mut response = vec![0; 4096];
+
+        let separate = messages(
+            &mut [Operation::Write(&command), Operation::Read(&mut response)],
+            1_000_000,
+            8,
+            4096,
+        );
+        assert_eq!(lengths(&separate), [vec![4096, 4096]]);
+        assert!(cs_change_between_messages(&separate));
+
+        // Full duplex transfers use up both directions.
+        let mut buffer = vec![0; 4000];
+        let mut response = vec![0; 200];
+
+        let duplex = messages(
+            &mut [
+                Operation::Transfer(&mut buffer),
+                Operation::Read(&mut response),
+            ],
+            1_000_000,
+            8,
+            4096,
+        );
+        assert_eq!(lengths(&duplex), [vec![4000], vec![200]]);
+        assert_eq!(duplex[0][0].tx_buf, duplex[0][0].rx_buf);
+        assert!(cs_change_between_messages(&duplex));
+    }
+
+    #[test]
+    fn splits_large_buffers() {
+        let mut buffer = vec![0; 10000];
+
+        let messages = messages(&mut [Operation::Read(&mut buffer)], 500_000, 16, 4096);
+
+        assert_eq!(lengths(&messages), [vec![4096], vec![4096], vec![1808]]);
+        assert!(cs_change_between_messages(&messages));
+        assert!(messages
+            .iter()
+            .flatten()
+            .all(|transfer| transfer.speed_hz == 500_000 && transfer.bits_per_word == 16));
+    }
+
+    #[test]
+    fn limits_transfers_per_message() {
+        let byte = [0xff];
+        let mut operations: Vec<Operation> = (0..SPI_IOC_MAX_TRANSFERS + 1)
+            .map(|_| Operation::Write(&byte))
+            .collect();
+
+        let messages = messages(&mut operations, 1_000_000, 8, usize::MAX / 2);
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].len(), SPI_IOC_MAX_TRANSFERS);
+        assert_eq!(messages[1].len(), 1);
+        assert!(cs_change_between_messages(&messages));
+    }
+
+    #[test]
+    fn single_message_keeps_chip_select() {
+        let data = [1, 2, 3];
+
+        let messages = messages(&mut [Operation::Write(&data)], 1_000_000, 8, 4096);
+
+        assert_eq!(lengths(&messages), [vec![3]]);
+        assert_eq!(messages[0][0].cs_change, 0);
+    }
+}
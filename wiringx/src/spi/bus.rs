@@ -0,0 +1,316 @@
+//! Shared SPI buses serving many devices.
+
+use std::{
+    fs::OpenOptions,
+    io,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    gpio::{Output, Pin, Value},
+    Hand, WiringXError,
+};
+
+use super::{
+    ioctl::{self, SPI_NO_CS},
+    spi_error, transfer, Operation, SpiConfig,
+};
+
+/// Chip select of a device on an `SpiBus`.
+///
+/// GPIO chip selects need a controller accepting `SPI_NO_CS`, which keeps the native chip select idle during their
+/// transactions. Other controllers toggle the native chip select along, so the native one and GPIO ones can not be
+/// mixed on such a bus: whichever kind comes second gets `Unsupported`.
+#[derive(Debug)]
+pub enum ChipSelect {
+    /// The chip select line of the spidev device, driven by the controller.
+    ///
+    /// Only one device per bus can use it.
+    Native,
+    /// A GPIO driven around every transaction of the device.
+    Gpio(Pin<Output>),
+}
+
+/// State shared between a bus and all of its devices.
+#[derive(Debug)]
+struct BusInner {
+    dev: PathBuf,
+    fd: OwnedFd,
+    /// Current settings of the file descriptor. Locking it grants exclusive access to the bus.
+    state: Mutex<BusState>,
+    native_used: Mutex<bool>,
+    handles: Hand<PathBuf>,
+}
+
+/// Per file descriptor settings of the kernel, switched between devices.
+#[derive(Debug)]
+struct BusState {
+    /// Mode flags the file descriptor is currently set to.
+    mode: Option<u32>,
+    /// Whether the controller accepts `SPI_NO_CS`, keeping its own chip select idle for GPIO chip selects.
+    no_cs: bool,
+}
+
+impl Drop for BusInner {
+    fn drop(&mut self) {
+        self.handles.lock().remove(&self.dev);
+    }
+}
+
+/// An SPI bus like `/dev/spidev0.0`, owning a single file descriptor for all devices on it.
+///
+/// Cloning the bus is cheap and returns a handle to the same bus. The file descriptor gets closed once the bus and
+/// all of its devices are dropped.
+#[derive(Debug, Clone)]
+pub struct SpiBus {
+    inner: Arc<BusInner>,
+}
+
+impl SpiBus {
+    pub(crate) fn new(dev: PathBuf, handles: Hand<PathBuf>) -> Result<Self, WiringXError> {
+        if handles.lock().contains(&dev) {
+            return Err(WiringXError::PinUsed);
+        }
+
+        let fd = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&dev)
+            .map_err(WiringXError::Io)?
+            .into();
+
+        handles.lock().insert(dev.clone());
+
+        Ok(Self {
+            inner: Arc::new(BusInner {
+                dev,
+                fd,
+                state: Mutex::new(BusState {
+                    mode: None,
+                    no_cs: true,
+                }),
+                native_used: Mutex::new(false),
+                handles,
+            }),
+        })
+    }
+
+    /// Returns the device path of this bus.
+    pub fn path(&self) -> &Path {
+        &self.inner.dev
+    }
+
+    /// Returns the raw file descriptor of this bus.
+    pub fn get_fd(&self) -> RawFd {
+        self.inner.fd.as_raw_fd()
+    }
+
+    /// Returns a handle to a device on this bus with its own chip select and transfer settings.
+    ///
+    /// A GPIO chip select gets driven to its inactive level right away. Returns `Unsupported` if the controller
+    /// rejects the mode of the configuration, for example three wire or dual and quad transfers, or if the native and
+    /// GPIO chip selects can not share this bus, see `ChipSelect`.
+    pub fn device(&self, cs: ChipSelect, config: SpiConfig) -> Result<SpiDevice, WiringXError> {
+        match &cs {
+            ChipSelect::Native => {
+                // The native chip select gets asserted during the transactions of GPIO chip select devices.
+                if !self.inner.state.lock().no_cs {
+                    return Err(WiringXError::Unsupported);
+                }
+
+                let mut native_used = self.inner.native_used.lock();
+                if *native_used {
+                    return Err(WiringXError::PinUsed);
+                }
+                *native_used = true;
+            }
+            ChipSelect::Gpio(pin) => pin.write(inactive_level(&config)),
+        }
+
+        let device = SpiDevice {
+            bus: self.inner.clone(),
+            cs,
+            config,
+        };
+        device.apply_mode()?;
+
+        Ok(device)
+    }
+}
+
+/// A device on an `SpiBus`, selected by its chip select.
+///
+/// Every transaction switches the bus to the settings of this device while holding the bus lock.
+#[derive(Debug)]
+pub struct SpiDevice {
+    bus: Arc<BusInner>,
+    cs: ChipSelect,
+    config: SpiConfig,
+}
+
+impl SpiDevice {
+    /// Returns a handle to the bus this device is on.
+    pub fn bus(&self) -> SpiBus {
+        SpiBus {
+            inner: self.bus.clone(),
+        }
+    }
+
+    /// Returns the transfer settings of this device.
+    pub fn config(&self) -> SpiConfig {
+        self.config
+    }
+
+    /// Changes the transfer settings of this device.
+    ///
+    /// Returns `Unsupported` and keeps the previous settings if the controller rejects the new mode.
+    pub fn set_config(&mut self, config: SpiConfig) -> Result<(), WiringXError> {
+        let previous = std::mem::replace(&mut self.config, config);
+
+        if let Err(error) = self.apply_mode() {
+            self.config = previous;
+            return Err(error);
+        }
+
+        if let ChipSelect::Gpio(pin) = &self.cs {
+            pin.write(inactive_level(&config));
+        }
+
+        Ok(())
+    }
+
+    /// Switches the bus to the mode of this device, checking that the controller supports it.
+    fn apply_mode(&self) -> Result<(), WiringXError> {
+        let mut state = self.bus.state.lock();
+
+        self.select_mode(&mut state, self.bus.fd.as_raw_fd())
+            .map_err(spi_error)
+    }
+
+    /// Executes all operations with the chip select asserted throughout.
+    ///
+    /// Transactions larger than `spi::buffer_size` get split into multiple messages. A GPIO chip select stays
+    /// asserted in between, the native one only if the controller supports it.
+    ///
+    /// Returns `Unsupported` for full duplex transfers on three wire devices, for transfer widths beyond the
+    /// configured ones and for GPIO chip selects that would select a native chip select device along.
+    pub fn transaction(&self, operations: &mut [Operation]) -> Result<(), WiringXError> {
+        if self.config.three_wire
+            && operations
+                .iter()
+                .any(|operation| matches!(operation, Operation::Transfer(_)))
+        {
+            return Err(WiringXError::Unsupported);
+        }
+
+        let mut state = self.bus.state.lock();
+        let fd = self.bus.fd.as_raw_fd();
+
+        self.select_mode(&mut state, fd).map_err(spi_error)?;
+
+        let speed = self.config.speed;
+        let bits_per_word = self.config.bits_per_word;
+
+        let ChipSelect::Gpio(pin) = &self.cs else {
+            return transfer(fd, operations, speed, bits_per_word).map_err(spi_error);
+        };
+
+        pin.write(active_level(&self.config));
+        let result = transfer(fd, operations, speed, bits_per_word);
+        pin.write(inactive_level(&self.config));
+
+        result.map_err(spi_error)
+    }
+
+    /// Switches the file descriptor to the mode of this device.
+    ///
+    /// Fails with `EINVAL` for GPIO chip select devices if the controller would assert the chip select of a native
+    /// chip select device along.
+    fn select_mode(&self, state: &mut BusState, fd: RawFd) -> io::Result<()> {
+        let gpio = matches!(self.cs, ChipSelect::Gpio(_));
+        let mut mode = self.config.mode_flags();
+
+        if gpio && state.no_cs {
+            mode |= SPI_NO_CS;
+        } else if gpio && *self.bus.native_used.lock() {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+
+        if state.mode == Some(mode) {
+            return Ok(());
+        }
+
+        // Forget the mode on failure, the kernel state is unknown.
+        state.mode = None;
+
+        match ioctl::set_mode(fd, mode) {
+            // The controller always drives its chip select, which then toggles along with the GPIO.
+            Err(error) if mode & SPI_NO_CS != 0 && error.raw_os_error() == Some(libc::EINVAL) => {
+                state.no_cs = false;
+
+                if *self.bus.native_used.lock() {
+                    return Err(error);
+                }
+
+                mode &= !SPI_NO_CS;
+                ioctl::set_mode(fd, mode)?;
+            }
+            result => result?,
+        }
+
+        state.mode = Some(mode);
+
+        Ok(())
+    }
+
+    /// Writes the data to the device and overwrites it with the data read from the device.
+    pub fn read_write(&self, data: &mut [u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [Operation::Transfer(data)])
+    }
+
+    /// Writes the data to the device, discarding the received data.
+    pub fn write(&self, data: &[u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [Operation::Write(data)])
+    }
+
+    /// Fills the buffer with data read from the device.
+    pub fn read(&self, data: &mut [u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [Operation::Read(data)])
+    }
+
+    /// Writes the given bytes and reads into the buffer afterwards, keeping the chip select asserted.
+    ///
+    /// This is the half duplex exchange of three wire devices, which turn the shared data line around between the
+    /// write and the read phase.
+    pub fn write_read(&self, write: &[u8], read: &mut [u8]) -> Result<(), WiringXError> {
+        self.transaction(&mut [Operation::Write(write), Operation::Read(read)])
+    }
+}
+
+impl Drop for SpiDevice {
+    fn drop(&mut self) {
+        if let ChipSelect::Native = self.cs {
+            *self.bus.native_used.lock() = false;
+        }
+    }
+}
+
+fn active_level(config: &SpiConfig) -> Value {
+    if config.cs_active_high {
+        Value::High
+    } else {
+        Value::Low
+    }
+}
+
+fn inactive_level(config: &SpiConfig) -> Value {
+    if config.cs_active_high {
+        Value::Low
+    } else {
+        Value::High
+    }
+}
@@ -0,0 +1,127 @@
+//! Definitions of the Linux spidev userspace interface.
+
+use std::{io, mem::size_of, os::fd::RawFd};
+
+/// Clock phase, sampling on the second edge.
+pub(crate) const SPI_CPHA: u32 = 0x01;
+/// Clock polarity, idling high.
+pub(crate) const SPI_CPOL: u32 = 0x02;
+/// Chip select is active high.
+pub(crate) const SPI_CS_HIGH: u32 = 0x04;
+/// Words are sent least significant bit first.
+pub(crate) const SPI_LSB_FIRST: u32 = 0x08;
+/// Data in and out share a single line.
+pub(crate) const SPI_3WIRE: u32 = 0x10;
+/// The controller does not drive its chip select.
+pub(crate) const SPI_NO_CS: u32 = 0x40;
+/// Sending over two data lines is allowed.
+pub(crate) const SPI_TX_DUAL: u32 = 0x100;
+/// Sending over four data lines is allowed.
+pub(crate) const SPI_TX_QUAD: u32 = 0x200;
+/// Receiving over two data lines is allowed.
+pub(crate) const SPI_RX_DUAL: u32 = 0x400;
+/// Receiving over four data lines is allowed.
+pub(crate) const SPI_RX_QUAD: u32 = 0x800;
+
+/// Reads the word size of the device, zero meaning 8 bits.
+const SPI_IOC_RD_BITS_PER_WORD: u32 = 0x8001_6b03;
+/// Reads the maximum clock speed of the device.
+const SPI_IOC_RD_MAX_SPEED_HZ: u32 = 0x8004_6b04;
+/// Reads the mode flags of the device.
+const SPI_IOC_RD_MODE32: u32 = 0x8004_6b05;
+/// Sets the mode flags of the device.
+const SPI_IOC_WR_MODE32: u32 = 0x4004_6b05;
+
+/// Maximum number of transfers in a single message, limited by the size field of the ioctl number.
+pub(crate) const SPI_IOC_MAX_TRANSFERS: usize = 0x3fff / size_of::<SpiIocTransfer>();
+
+/// A single segment of an SPI message. Chip select stays asserted between the transfers of a message.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct SpiIocTransfer {
+    pub(crate) tx_buf: u64,
+    pub(crate) rx_buf: u64,
+    pub(crate) len: u32,
+    pub(crate) speed_hz: u32,
+    pub(crate) delay_usecs: u16,
+    pub(crate) bits_per_word: u8,
+    pub(crate) cs_change: u8,
+    pub(crate) tx_nbits: u8,
+    pub(crate) rx_nbits: u8,
+    pub(crate) word_delay_usecs: u8,
+    pub(crate) pad: u8,
+}
+
+/// Number of the ioctl sending a message of the given number of transfers.
+fn spi_ioc_message(transfers: usize) -> u32 {
+    0x4000_6b00 | ((transfers * size_of::<SpiIocTransfer>()) as u32) << 16
+}
+
+/// Sends all transfers as one message on the given spidev file descriptor.
+pub(crate) fn message(fd: RawFd, transfers: &mut [SpiIocTransfer]) -> io::Result<()> {
+    let result = unsafe {
+        libc::ioctl(
+            fd,
+            spi_ioc_message(transfers.len()) as _,
+            transfers.as_mut_ptr(),
+        )
+    };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Sets the mode flags of the given spidev file descriptor.
+pub(crate) fn set_mode(fd: RawFd, mode: u32) -> io::Result<()> {
+    let result = unsafe { libc::ioctl(fd, SPI_IOC_WR_MODE32 as _, &mode) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads the mode flags of the given spidev file descriptor.
+pub(crate) fn mode(fd: RawFd) -> io::Result<u32> {
+    let mut mode: u32 = 0;
+
+    let result = unsafe { libc::ioctl(fd, SPI_IOC_RD_MODE32 as _, &mut mode) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(mode)
+    }
+}
+
+/// Reads the word size of the given spidev file descriptor.
+pub(crate) fn bits_per_word(fd: RawFd) -> io::Result<u8> {
+    let mut bits: u8 = 0;
+
+    let result = unsafe { libc::ioctl(fd, SPI_IOC_RD_BITS_PER_WORD as _, &mut bits) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else if bits == 0 {
+        Ok(8)
+    } else {
+        Ok(bits)
+    }
+}
+
+/// Reads the maximum clock speed of the given spidev file descriptor in Hertz.
+pub(crate) fn max_speed(fd: RawFd) -> io::Result<u32> {
+    let mut speed: u32 = 0;
+
+    let result = unsafe { libc::ioctl(fd, SPI_IOC_RD_MAX_SPEED_HZ as _, &mut speed) };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(speed)
+    }
+}
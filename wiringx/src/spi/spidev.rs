@@ -0,0 +1,115 @@
+//! Discovery of the spidev devices of the system.
+
+use std::{
+    fs::{self, OpenOptions},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use crate::WiringXError;
+
+use super::{
+    ioctl::{
+        self, SPI_3WIRE, SPI_CPHA, SPI_CPOL, SPI_CS_HIGH, SPI_LSB_FIRST, SPI_RX_DUAL, SPI_RX_QUAD,
+        SPI_TX_DUAL, SPI_TX_QUAD,
+    },
+    BusWidth, SpiConfig, SpiMode,
+};
+
+/// Directory containing the spidev device nodes.
+const DEV_DIR: &str = "/dev";
+
+/// A spidev device of the system with its current settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpidevInfo {
+    /// Path of the device node, for example `/dev/spidev1.0`.
+    pub path: PathBuf,
+    /// Number of the SPI controller.
+    pub bus: u32,
+    /// Chip select of the controller this device uses.
+    pub cs: u32,
+    /// Settings the device is currently set to, with the maximum speed as speed.
+    pub config: SpiConfig,
+    /// All spidev mode flags of the device, including those not covered by `config`.
+    pub mode_flags: u32,
+}
+
+/// Returns the path of the spidev device of the given controller and chip select, like `/dev/spidev1.0`.
+pub fn spidev_path(bus: u32, cs: u32) -> PathBuf {
+    Path::new(DEV_DIR).join(format!("spidev{bus}.{cs}"))
+}
+
+/// Lists all spidev devices of the system, ordered by controller and chip select.
+///
+/// The settings get read back from the kernel, so devices in use by other processes may report their settings.
+/// Devices that cannot be opened or queried, for example for lack of permissions, get skipped with a warning.
+pub fn available_devices() -> Result<Vec<SpidevInfo>, WiringXError> {
+    let mut devices = Vec::new();
+
+    for entry in fs::read_dir(DEV_DIR).map_err(WiringXError::Io)? {
+        let entry = entry.map_err(WiringXError::Io)?;
+
+        let Some((bus, cs)) = entry.file_name().to_str().and_then(parse_name) else {
+            continue;
+        };
+
+        match device_info(entry.path(), bus, cs) {
+            Ok(device) => devices.push(device),
+            Err(error) => log::warn!("Skipping spidev device {}: {error}", entry.path().display()),
+        }
+    }
+
+    devices.sort_by_key(|device| (device.bus, device.cs));
+
+    Ok(devices)
+}
+
+/// Parses the controller and chip select out of a device name like `spidev1.0`.
+fn parse_name(name: &str) -> Option<(u32, u32)> {
+    let (bus, cs) = name.strip_prefix("spidev")?.split_once('.')?;
+
+    Some((bus.parse().ok()?, cs.parse().ok()?))
+}
+
+fn device_info(path: PathBuf, bus: u32, cs: u32) -> std::io::Result<SpidevInfo> {
+    let file = OpenOptions::new().read(true).write(true).open(&path)?;
+    let fd = file.as_raw_fd();
+
+    let mode_flags = ioctl::mode(fd)?;
+
+    let mode = match mode_flags & (SPI_CPOL | SPI_CPHA) {
+        0 => SpiMode::Mode0,
+        SPI_CPHA => SpiMode::Mode1,
+        SPI_CPOL => SpiMode::Mode2,
+        _ => SpiMode::Mode3,
+    };
+
+    let config = SpiConfig {
+        mode,
+        speed: ioctl::max_speed(fd)?,
+        bits_per_word: ioctl::bits_per_word(fd)?,
+        lsb_first: mode_flags & SPI_LSB_FIRST != 0,
+        cs_active_high: mode_flags & SPI_CS_HIGH != 0,
+        three_wire: mode_flags & SPI_3WIRE != 0,
+        tx_width: bus_width(mode_flags, SPI_TX_DUAL, SPI_TX_QUAD),
+        rx_width: bus_width(mode_flags, SPI_RX_DUAL, SPI_RX_QUAD),
+    };
+
+    Ok(SpidevInfo {
+        path,
+        bus,
+        cs,
+        config,
+        mode_flags,
+    })
+}
+
+fn bus_width(mode_flags: u32, dual: u32, quad: u32) -> BusWidth {
+    if mode_flags & quad != 0 {
+        BusWidth::Quad
+    } else if mode_flags & dual != 0 {
+        BusWidth::Dual
+    } else {
+        BusWidth::Single
+    }
+}
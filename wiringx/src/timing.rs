@@ -0,0 +1,293 @@
+//! Precise timing and real-time scheduling helpers for control loops.
+
+use std::{
+    hint, io, mem, thread,
+    time::{Duration, Instant},
+};
+
+use wiringx_sys::delayMicroseconds;
+
+use crate::WiringXError;
+
+/// Remaining time below which waits busy-spin instead of sleeping, as the scheduler can not wake up precisely enough.
+const SPIN_THRESHOLD: Duration = Duration::from_micros(100);
+
+/// Suspends the thread for the given amount of microseconds using wiringX.
+///
+/// Delays shorter than 100 microseconds are busy-waited, longer ones sleep.
+pub fn delay_microseconds(micros: u32) {
+    unsafe { delayMicroseconds(micros) }
+}
+
+/// Blocks until the deadline is reached.
+///
+/// Sleeps for most of the time and busy-waits for the last 100 microseconds to reach microsecond accuracy.
+pub fn sleep_until(deadline: Instant) {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining > SPIN_THRESHOLD {
+        thread::sleep(remaining - SPIN_THRESHOLD);
+    }
+
+    while Instant::now() < deadline {
+        hint::spin_loop();
+    }
+}
+
+/// Blocks for the given duration with microsecond accuracy.
+///
+/// See `sleep_until`.
+pub fn sleep_precise(duration: Duration) {
+    sleep_until(Instant::now() + duration);
+}
+
+/// Outcome of a single `Ticker` period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    /// Number of deadlines that passed before this tick without being waited on.
+    ///
+    /// Zero means the loop kept up with the period.
+    pub missed: u64,
+    /// Time between the deadline of this tick and the moment the thread woke up.
+    pub lateness: Duration,
+}
+
+/// Periodic timer for control loops, sleeping on absolute deadlines of the monotonic clock.
+///
+/// Absolute deadlines prevent the period from drifting by the time the loop body takes.
+#[derive(Debug)]
+pub struct Ticker {
+    period: Duration,
+    /// Next deadline as time since the start of the monotonic clock.
+    next: Duration,
+    ticks: u64,
+    missed: u64,
+}
+
+impl Ticker {
+    /// Creates a ticker with the given period. The first deadline is one period from now.
+    pub fn new(period: Duration) -> Result<Self, WiringXError> {
+        if period.is_zero() {
+            return Err(WiringXError::Other(
+                "The period of a ticker can not be zero.".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            period,
+            next: monotonic_now()? + period,
+            ticks: 0,
+            missed: 0,
+        })
+    }
+
+    /// Returns the period of this ticker.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the number of ticks waited on so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Returns the number of deadlines missed since creation.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Sleeps until the next deadline.
+    ///
+    /// If the loop fell behind by more than a period, the missed deadlines get skipped and reported instead of
+    /// returning immediately for each of them.
+    pub fn tick(&mut self) -> Result<Tick, WiringXError> {
+        let (next, missed) = catch_up(self.next, self.period, monotonic_now()?);
+        self.next = next;
+        self.missed = self.missed.saturating_add(missed);
+
+        let deadline = libc::timespec {
+            tv_sec: self.next.as_secs() as libc::time_t,
+            tv_nsec: self.next.subsec_nanos() as libc::c_long,
+        };
+
+        loop {
+            let result = unsafe {
+                libc::clock_nanosleep(
+                    libc::CLOCK_MONOTONIC,
+                    libc::TIMER_ABSTIME,
+                    &deadline,
+                    std::ptr::null_mut(),
+                )
+            };
+
+            match result {
+                0 => break,
+                libc::EINTR => continue,
+                error => return Err(WiringXError::Io(io::Error::from_raw_os_error(error))),
+            }
+        }
+
+        let lateness = monotonic_now()?.saturating_sub(self.next);
+
+        self.next += self.period;
+        self.ticks += 1;
+
+        Ok(Tick { missed, lateness })
+    }
+}
+
+/// Skips the deadlines passed by `now`, returning the next deadline still ahead and how many were skipped.
+fn catch_up(next: Duration, period: Duration, now: Duration) -> (Duration, u64) {
+    let period = period.as_nanos();
+    let missed = now.saturating_sub(next).as_nanos() / period;
+
+    // Never more than the time behind, so the deadline stays a valid duration.
+    let skipped = period * missed;
+    let skipped = Duration::new(
+        (skipped / 1_000_000_000) as u64,
+        (skipped % 1_000_000_000) as u32,
+    );
+
+    (next + skipped, u64::try_from(missed).unwrap_or(u64::MAX))
+}
+
+/// Switches the calling thread to the `SCHED_FIFO` real-time scheduling policy with the given priority.
+///
+/// Valid priorities on Linux range from 1 to 99. Requires root or the `CAP_SYS_NICE` capability.
+pub fn set_realtime_priority(priority: i32) -> Result<(), WiringXError> {
+    let (min, max) = unsafe {
+        (
+            libc::sched_get_priority_min(libc::SCHED_FIFO),
+            libc::sched_get_priority_max(libc::SCHED_FIFO),
+        )
+    };
+
+    if !(min..=max).contains(&priority) {
+        return Err(WiringXError::Other(format!(
+            "Real-time priority must be between {min} and {max}."
+        )));
+    }
+
+    set_scheduler(libc::SCHED_FIFO, priority)
+}
+
+/// Switches the calling thread back to the default time sharing scheduling policy.
+pub fn set_normal_priority() -> Result<(), WiringXError> {
+    set_scheduler(libc::SCHED_OTHER, 0)
+}
+
+fn set_scheduler(policy: libc::c_int, priority: i32) -> Result<(), WiringXError> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+
+    let result = unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy, &param) };
+
+    if result != 0 {
+        Err(WiringXError::Io(io::Error::from_raw_os_error(result)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Restricts the calling thread to run only on the given CPU cores.
+pub fn set_cpu_affinity(cpus: &[usize]) -> Result<(), WiringXError> {
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(WiringXError::Other(format!(
+                "CPU {cpu} exceeds the supported number of cores."
+            )));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+
+    let result = unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) };
+
+    if result < 0 {
+        Err(WiringXError::Io(io::Error::last_os_error()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Locks all current and future memory pages of the process into RAM, preventing page faults in timing critical
+/// code.
+///
+/// This affects the whole process, not only the calling thread.
+pub fn lock_memory() -> Result<(), WiringXError> {
+    let result = unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) };
+
+    if result < 0 {
+        Err(WiringXError::Io(io::Error::last_os_error()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Unlocks all memory pages of the process locked using `lock_memory`.
+pub fn unlock_memory() -> Result<(), WiringXError> {
+    let result = unsafe { libc::munlockall() };
+
+    if result < 0 {
+        Err(WiringXError::Io(io::Error::last_os_error()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the time since the start of the monotonic clock, the clock `Ticker` deadlines are based on.
+fn monotonic_now() -> Result<Duration, WiringXError> {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    let result = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+
+    if result < 0 {
+        Err(WiringXError::Io(io::Error::last_os_error()))
+    } else {
+        Ok(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catch_up_skips_missed_deadlines() {
+        let next = Duration::from_secs(5);
+        let period = Duration::from_millis(10);
+
+        assert_eq!(catch_up(next, period, Duration::from_secs(4)), (next, 0));
+        assert_eq!(
+            catch_up(next, period, next + Duration::from_millis(9)),
+            (next, 0)
+        );
+        assert_eq!(
+            catch_up(next, period, next + Duration::from_millis(25)),
+            (next + Duration::from_millis(20), 2)
+        );
+    }
+
+    #[test]
+    fn catch_up_after_long_stall() {
+        let next = Duration::from_secs(5);
+
+        // More missed deadlines than fit into a u32.
+        let (deadline, missed) = catch_up(
+            next,
+            Duration::from_nanos(1),
+            next + Duration::from_secs(10),
+        );
+        assert_eq!(missed, 10_000_000_000);
+        assert_eq!(deadline, next + Duration::from_secs(10));
+
+        let stall = Duration::from_secs(3 * 3600) + Duration::from_nanos(1);
+        let (deadline, missed) = catch_up(next, Duration::from_micros(3), next + stall);
+        assert_eq!(missed, 3_600_000_000);
+        assert_eq!(deadline, next + Duration::from_secs(3 * 3600));
+    }
+}
@@ -1,11 +1,29 @@
 //! Universal asynchronous receiver/transmitter serial communication related objects.
 
+#[cfg(feature = "async")]
+mod async_uart;
+#[cfg(feature = "async")]
+pub mod codec;
+mod modem;
+mod ports;
+mod split;
+mod termios;
+
+#[cfg(feature = "async")]
+pub use async_uart::AsyncUart;
+pub use modem::{LineCounters, LineEvent, ModemLine, ModemStatus};
+pub use ports::{available_ports, SerialPortInfo, UsbInfo};
+pub use split::{UartReader, UartWriter};
+
 use std::{
     ffi::{c_uchar, c_uint, CString},
-    os::fd::RawFd,
+    io,
+    os::fd::{AsRawFd, RawFd},
     path::PathBuf,
+    time::{Duration, Instant},
 };
 
+use parking_lot::Mutex;
 use thiserror::Error;
 use wiringx_sys::{
     wiringXSerialClose, wiringXSerialDataAvail, wiringXSerialFlush, wiringXSerialGetChar,
@@ -14,50 +32,37 @@ use wiringx_sys::{
 
 use crate::{Hand, WiringXError};
 
+/// Baud rates tried by `Uart::auto_baud` by default, the most common ones first.
+pub const COMMON_BAUD_RATES: [u32; 11] = [
+    115200, 9600, 57600, 38400, 19200, 230400, 460800, 921600, 4800, 2400, 1200,
+];
+
 /// Configuration of the serial connection.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SerialConfig {
     pub baud_rate: u32,
     pub data_bits: u32,
     pub parity: Parity,
-    pub stop_bits: u32,
+    pub stop_bits: StopBits,
     pub flow_control: FlowControl,
 }
 
 impl SerialConfig {
     /// Checks if the configuration provided in this struct is valid and usable in wiringX.
     pub fn check(&self) -> Result<(), InvalidUARTConfig> {
-        match self.baud_rate {
-            50 => (),
-            75 => (),
-            110 => (),
-            134 => (),
-            150 => (),
-            200 => (),
-            300 => (),
-            600 => (),
-            1200 => (),
-            1800 => (),
-            2400 => (),
-            4800 => (),
-            9600 => (),
-            19200 => (),
-            38400 => (),
-            57600 => (),
-            115200 => (),
-            230400 => (),
-            _ => return Err(InvalidUARTConfig::BaudRate),
-        };
+        if termios::baud_constant(self.baud_rate).is_none() {
+            return Err(InvalidUARTConfig::BaudRate);
+        }
 
-        match self.data_bits {
-            7 => (),
-            8 => (),
-            _ => return Err(InvalidUARTConfig::DataBits),
-        };
+        if !(5..=8).contains(&self.data_bits) {
+            return Err(InvalidUARTConfig::DataBits);
+        }
 
-        match self.stop_bits {
-            1 => (),
-            2 => (),
+        // UARTs send 1.5 stop bits only with 5 data bits, where 2 are not possible.
+        match (self.stop_bits, self.data_bits) {
+            (StopBits::One, _) => (),
+            (StopBits::OneAndHalf, 5) => (),
+            (StopBits::Two, 6..=8) => (),
             _ => return Err(InvalidUARTConfig::StopBits),
         };
 
@@ -67,10 +72,15 @@ impl SerialConfig {
 
 impl From<SerialConfig> for wiringXSerial_t {
     fn from(rh: SerialConfig) -> Self {
+        // wiringX knows no stick parity, mark and space parity get applied to the file descriptor afterwards.
         let parity = match rh.parity {
             Parity::Odd => 'o' as c_uint,
             Parity::Even => 'e' as c_uint,
-            Parity::None => 'n' as c_uint,
+            Parity::None | Parity::Mark | Parity::Space => 'n' as c_uint,
+        };
+        let stop_bits = match rh.stop_bits {
+            StopBits::One => 1,
+            StopBits::OneAndHalf | StopBits::Two => 2,
         };
         let flow_control = match rh.flow_control {
             FlowControl::XOnOff => 'x' as c_uint,
@@ -80,7 +90,7 @@ impl From<SerialConfig> for wiringXSerial_t {
             baud: rh.baud_rate,
             databits: rh.data_bits,
             parity,
-            stopbits: rh.stop_bits,
+            stopbits: stop_bits,
             flowcontrol: flow_control,
         }
     }
@@ -92,6 +102,9 @@ pub struct Uart {
     fd: RawFd,
     dev: PathBuf,
     handles: Hand<PathBuf>,
+    marking: Mutex<modem::LineMarking>,
+    /// Whether the device was opened by wiringX, which has to close it again.
+    opened_by_wiringx: bool,
 }
 
 impl Uart {
@@ -111,18 +124,69 @@ impl Uart {
         ))?)
         .map_err(|e| WiringXError::Other(e.to_string()))?;
 
-        let fd_result = unsafe { wiringXSerialOpen(path_string.as_ptr(), config.into()) };
+        // Opened with framing wiringX understands, the actual configuration gets applied afterwards.
+        let base = SerialConfig {
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: config.flow_control,
+        };
+
+        let fd_result = unsafe { wiringXSerialOpen(path_string.as_ptr(), base.into()) };
 
         if fd_result < 0 {
             return Err(WiringXError::Unsupported);
         }
 
+        let applied = termios::get(fd_result).and_then(|mut settings| {
+            termios::configure(&mut settings, &config)?;
+            termios::set(fd_result, &settings, libc::TCSANOW)
+        });
+
+        if let Err(error) = applied {
+            unsafe { wiringXSerialClose(fd_result) };
+            return Err(WiringXError::Io(error));
+        }
+
         handles.lock().insert(dev.clone());
 
         Ok(Self {
             fd: fd_result,
             dev,
             handles,
+            marking: Mutex::default(),
+            opened_by_wiringx: true,
+        })
+    }
+
+    /// Wraps a terminal opened outside of wiringX, like one side of a pseudo terminal, in raw mode with the
+    /// configuration applied.
+    ///
+    /// The terminal is not registered with `WiringX` and gets closed with the UART.
+    #[cfg(test)]
+    pub(crate) fn from_fd(
+        fd: std::os::fd::OwnedFd,
+        config: SerialConfig,
+    ) -> Result<Self, WiringXError> {
+        use std::os::fd::IntoRawFd;
+
+        config.check().map_err(WiringXError::InvalidUARTConfig)?;
+
+        let mut settings = termios::get(fd.as_raw_fd()).map_err(WiringXError::Io)?;
+        unsafe { libc::cfmakeraw(&mut settings) };
+        termios::configure(&mut settings, &config).map_err(WiringXError::Io)?;
+        termios::set(fd.as_raw_fd(), &settings, libc::TCSANOW).map_err(WiringXError::Io)?;
+
+        let dev =
+            std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).unwrap_or_default();
+
+        Ok(Self {
+            fd: fd.into_raw_fd(),
+            dev,
+            handles: Hand::default(),
+            marking: Mutex::default(),
+            opened_by_wiringx: false,
         })
     }
 
@@ -152,11 +216,233 @@ impl Uart {
     pub fn read_char(&self) -> char {
         unsafe { char::from_u32_unchecked(wiringXSerialGetChar(self.fd) as u32) }
     }
+
+    /// Writes all bytes to the UART.
+    pub fn write(&self, data: &[u8]) -> Result<(), WiringXError> {
+        write_all(self.fd, data).map_err(WiringXError::Io)
+    }
+
+    /// Waits until all written bytes have left the UART.
+    pub fn drain(&self) -> Result<(), WiringXError> {
+        if unsafe { libc::tcdrain(self.fd) } < 0 {
+            Err(WiringXError::Io(io::Error::last_os_error()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads the received bytes into the buffer, waiting up to the timeout for the first one.
+    ///
+    /// Returns the number of bytes read, zero on timeout.
+    pub fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, WiringXError> {
+        if !wait_readable(self.fd, timeout).map_err(WiringXError::Io)? {
+            return Ok(0);
+        }
+
+        let read = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+        if read < 0 {
+            Err(WiringXError::Io(io::Error::last_os_error()))
+        } else {
+            Ok(read as usize)
+        }
+    }
+
+    /// Applies the configuration without closing the device.
+    ///
+    /// The change takes effect after all pending output was sent, received data is kept.
+    pub fn reconfigure(&self, config: SerialConfig) -> Result<(), WiringXError> {
+        config.check().map_err(WiringXError::InvalidUARTConfig)?;
+
+        let mut settings = termios::get(self.fd).map_err(WiringXError::Io)?;
+        termios::configure(&mut settings, &config).map_err(WiringXError::Io)?;
+
+        termios::set(self.fd, &settings, libc::TCSADRAIN).map_err(WiringXError::Io)
+    }
+
+    /// Reads the current configuration back from the kernel.
+    pub fn config(&self) -> Result<SerialConfig, WiringXError> {
+        let settings = termios::get(self.fd).map_err(WiringXError::Io)?;
+
+        termios::config(&settings).map_err(WiringXError::Io)
+    }
+
+    /// Finds the baud rate of the other side by trying the given rates, for example `COMMON_BAUD_RATES`.
+    ///
+    /// For every rate the UART gets reconfigured, keeping the rest of the framing, the input gets discarded and the
+    /// probe gets sent, if not empty. Everything received within the window is passed to `accept`, which decides if
+    /// the data looks right, for example if it is readable text or a known reply to the probe.
+    ///
+    /// Returns the first accepted rate, which stays configured. Without one the previous configuration gets restored
+    /// and `None` returned.
+    pub fn auto_baud(
+        &self,
+        rates: &[u32],
+        probe: &[u8],
+        window: Duration,
+        mut accept: impl FnMut(&[u8]) -> bool,
+    ) -> Result<Option<u32>, WiringXError> {
+        let previous = self.config()?;
+
+        for &baud_rate in rates {
+            self.reconfigure(SerialConfig {
+                baud_rate,
+                ..previous
+            })?;
+
+            unsafe { libc::tcflush(self.fd, libc::TCIFLUSH) };
+            write_all(self.fd, probe).map_err(WiringXError::Io)?;
+
+            let received = read_for(self.fd, window).map_err(WiringXError::Io)?;
+
+            if !received.is_empty() && accept(&received) {
+                return Ok(Some(baud_rate));
+            }
+        }
+
+        self.reconfigure(previous)?;
+
+        Ok(None)
+    }
+
+    /// Sends the bytes as 9 bit words on a multidrop bus, using the parity bit as ninth bit.
+    ///
+    /// Address bytes are sent with the ninth bit set, data bytes without it. The parity switches to mark or space
+    /// parity after all pending output was sent and stays that way. The UART has to be set up with 8 data bits.
+    pub fn write_nine_bit(&self, data: &[u8], address: bool) -> Result<(), WiringXError> {
+        termios::set_stick_parity(self.fd, address).map_err(WiringXError::Io)?;
+
+        write_all(self.fd, data).map_err(WiringXError::Io)
+    }
+
+    /// Sends an address byte on a 9 bit multidrop bus, followed by the data bytes. See `write_nine_bit`.
+    pub fn write_nine_bit_frame(&self, address: u8, data: &[u8]) -> Result<(), WiringXError> {
+        self.write_nine_bit(&[address], true)?;
+        self.write_nine_bit(data, false)
+    }
+}
+
+/// Waits up to the timeout for the file descriptor to become readable, with microsecond resolution.
+fn wait_readable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timespec = libc::timespec {
+            tv_sec: remaining.as_secs() as libc::time_t,
+            tv_nsec: remaining.subsec_nanos() as libc::c_long,
+        };
+
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let result = unsafe { libc::ppoll(&mut poll_fd, 1, &timespec, std::ptr::null()) };
+
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+
+        return Ok(result > 0);
+    }
+}
+
+/// Converts the timeout to the milliseconds of `poll`.
+///
+/// Rounded up, as a timeout of zero would spin through the last millisecond, and clamped, as negative timeouts
+/// wait forever.
+fn poll_timeout(timeout: Duration) -> libc::c_int {
+    timeout
+        .as_nanos()
+        .div_ceil(1_000_000)
+        .min(libc::c_int::MAX as u128) as libc::c_int
+}
+
+/// Collects everything received on the file descriptor until the window has passed.
+fn read_for(fd: RawFd, window: Duration) -> io::Result<Vec<u8>> {
+    let deadline = Instant::now() + window;
+    let mut received = Vec::new();
+    let mut buffer = [0u8; 256];
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(received);
+        }
+
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let result = unsafe { libc::poll(&mut poll_fd, 1, poll_timeout(remaining)) };
+
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+
+        if result == 0 {
+            continue;
+        }
+
+        let read =
+            unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+
+        if read < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+
+        received.extend_from_slice(&buffer[..read as usize]);
+    }
+}
+
+/// Writes all bytes to the file descriptor, retrying on interrupts and partial writes.
+fn write_all(fd: RawFd, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        let written = unsafe { libc::write(fd, data.as_ptr() as *const libc::c_void, data.len()) };
+
+        if written < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+
+        data = &data[written as usize..];
+    }
+
+    Ok(())
+}
+
+impl AsRawFd for Uart {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
 }
 
 impl Drop for Uart {
     fn drop(&mut self) {
-        unsafe { wiringXSerialClose(self.fd) }
+        if self.opened_by_wiringx {
+            unsafe { wiringXSerialClose(self.fd) }
+        } else {
+            unsafe { libc::close(self.fd) };
+        }
         self.handles.lock().remove(&self.dev);
     }
 }
@@ -170,6 +456,21 @@ pub enum Parity {
     Even,
     /// Odd parity
     Odd,
+    /// Parity bit always set, also used as ninth bit marking addresses on multidrop buses
+    Mark,
+    /// Parity bit always cleared
+    Space,
+}
+
+/// Number of UART stop bits.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum StopBits {
+    /// One stop bit
+    One,
+    /// One and a half stop bits, only with 5 data bits
+    OneAndHalf,
+    /// Two stop bits, not with 5 data bits
+    Two,
 }
 
 /// UART flow control
@@ -191,3 +492,76 @@ pub enum InvalidUARTConfig {
     #[error("The number of stop bits is not valid.")]
     StopBits,
 }
+
+/// Opens both sides of a pseudo terminal as UARTs with the configuration, to test protocols against a scripted peer.
+///
+/// Pseudo terminals ignore the baud rate and only support 8 data bits without parity.
+#[cfg(test)]
+pub(crate) fn pty_pair(config: SerialConfig) -> (Uart, Uart) {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let (mut controller, mut terminal) = (0, 0);
+    let result = unsafe {
+        libc::openpty(
+            &mut controller,
+            &mut terminal,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(result, 0, "{}", io::Error::last_os_error());
+
+    let (controller, terminal) = unsafe {
+        (
+            OwnedFd::from_raw_fd(controller),
+            OwnedFd::from_raw_fd(terminal),
+        )
+    };
+
+    (
+        Uart::from_fd(controller, config).unwrap(),
+        Uart::from_fd(terminal, config).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poll_timeout_rounds_up_and_clamps() {
+        assert_eq!(poll_timeout(Duration::ZERO), 0);
+        assert_eq!(poll_timeout(Duration::from_nanos(1)), 1);
+        assert_eq!(poll_timeout(Duration::from_micros(999)), 1);
+        assert_eq!(poll_timeout(Duration::from_millis(1)), 1);
+        assert_eq!(poll_timeout(Duration::from_micros(1001)), 2);
+        assert_eq!(poll_timeout(Duration::MAX), libc::c_int::MAX);
+    }
+
+    #[test]
+    fn reader_waits_for_short_timeouts() {
+        use std::io::Read;
+
+        let (uart, peer) = pty_pair(SerialConfig {
+            baud_rate: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        });
+        let (mut reader, _writer) = uart.split();
+        reader.set_timeout(Some(Duration::from_micros(500)));
+
+        let start = Instant::now();
+        let error = reader.read(&mut [0; 4]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_micros(500));
+
+        peer.write(b"ok").unwrap();
+        reader.set_timeout(Some(Duration::from_secs(1)));
+        let mut buffer = [0; 4];
+        assert_eq!(reader.read(&mut buffer).unwrap(), 2);
+        assert_eq!(&buffer[..2], b"ok");
+    }
+}
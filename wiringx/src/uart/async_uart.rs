@@ -0,0 +1,116 @@
+//! Asynchronous UART for the tokio runtime.
+
+use std::{
+    io,
+    os::fd::RawFd,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{unix::AsyncFd, AsyncRead, AsyncWrite, ReadBuf};
+
+use super::Uart;
+
+impl Uart {
+    /// Turns the UART into an asynchronous one, switching the device to non-blocking mode.
+    ///
+    /// Has to be called from within a tokio runtime with IO enabled. The device gets closed once the asynchronous
+    /// UART is dropped.
+    pub fn into_async(self) -> io::Result<AsyncUart> {
+        let flags = unsafe { libc::fcntl(self.fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(self.fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The UART owns its file descriptor and closes it only when dropped.
+        let inner = unsafe { AsyncFd::register(self)? };
+
+        Ok(AsyncUart { inner })
+    }
+}
+
+/// A UART implementing `AsyncRead` and `AsyncWrite`, created by `Uart::into_async`.
+///
+/// Works with `tokio_util::codec::Framed` and the codecs of this module, for example
+/// `Framed::new(uart, LineCodec::new())`.
+#[derive(Debug)]
+pub struct AsyncUart {
+    inner: AsyncFd<Uart>,
+}
+
+impl AsyncUart {
+    /// Returns the underlying UART, for example to reconfigure it or to drive the modem control lines.
+    ///
+    /// Blocking reads on it return immediately, since the device is in non-blocking mode.
+    pub fn get_ref(&self) -> &Uart {
+        self.inner.get_ref()
+    }
+}
+
+impl AsyncRead for AsyncUart {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_read_ready(cx))?;
+
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|inner| read(inner.get_ref().fd, unfilled)) {
+                Ok(result) => {
+                    buf.advance(result?);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncUart {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_write_ready(cx))?;
+
+            match guard.try_io(|inner| write(inner.get_ref().fd, buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Writes go straight to the kernel.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    let read = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+    if read < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(read as usize)
+    }
+}
+
+fn write(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    let written = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+
+    if written < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(written as usize)
+    }
+}
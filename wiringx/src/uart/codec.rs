@@ -0,0 +1,540 @@
+//! Codecs splitting serial byte streams into frames, for use with `tokio_util::codec::Framed`.
+
+use std::io;
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Frames longer than this get rejected by default, so garbage without delimiters does not grow the buffer forever.
+const DEFAULT_MAX_LENGTH: usize = 8192;
+
+fn too_long() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Frame exceeds the maximum length.",
+    )
+}
+
+/// Text lines ending with `\n`, with a trailing `\r` removed.
+///
+/// Lines get sent with the configured line ending, `\n` by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineCodec {
+    ending: &'static str,
+    max_length: usize,
+}
+
+impl LineCodec {
+    /// Creates a codec sending lines ending with `\n`.
+    pub fn new() -> Self {
+        Self {
+            ending: "\n",
+            max_length: DEFAULT_MAX_LENGTH,
+        }
+    }
+
+    /// Sets the line ending appended to sent lines, for example `\r\n`.
+    pub fn with_ending(mut self, ending: &'static str) -> Self {
+        self.ending = ending;
+        self
+    }
+
+    /// Sets the maximum length of received lines in bytes.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+}
+
+impl Default for LineCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for LineCodec {
+    type Item = String;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<String>> {
+        let Some(end) = src.iter().position(|&byte| byte == b'\n') else {
+            if src.len() > self.max_length {
+                return Err(too_long());
+            }
+            return Ok(None);
+        };
+
+        let line = src.split_to(end + 1);
+        let line = line
+            .strip_suffix(b"\n")
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .unwrap_or(&line);
+
+        if line.len() > self.max_length {
+            return Err(too_long());
+        }
+
+        String::from_utf8(line.to_vec())
+            .map(Some)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+impl<T: AsRef<str>> Encoder<T> for LineCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, line: T, dst: &mut BytesMut) -> io::Result<()> {
+        let line = line.as_ref();
+
+        dst.reserve(line.len() + self.ending.len());
+        dst.put_slice(line.as_bytes());
+        dst.put_slice(self.ending.as_bytes());
+
+        Ok(())
+    }
+}
+
+/// Frame delimiter of SLIP.
+const SLIP_END: u8 = 0xc0;
+/// Escape byte of SLIP.
+const SLIP_ESC: u8 = 0xdb;
+/// Escaped `SLIP_END`.
+const SLIP_ESC_END: u8 = 0xdc;
+/// Escaped `SLIP_ESC`.
+const SLIP_ESC_ESC: u8 = 0xdd;
+
+/// Serial Line Internet Protocol framing after RFC 1055.
+///
+/// Frames get sent with a leading and trailing `END` byte, empty frames get skipped when receiving.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlipCodec {
+    max_length: usize,
+}
+
+impl SlipCodec {
+    /// Creates a SLIP codec.
+    pub fn new() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_LENGTH,
+        }
+    }
+
+    /// Sets the maximum length of received frames in bytes, before unescaping.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+}
+
+impl Default for SlipCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for SlipCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let Some(end) = src.iter().position(|&byte| byte == SLIP_END) else {
+                if src.len() > self.max_length {
+                    return Err(too_long());
+                }
+                return Ok(None);
+            };
+
+            let encoded = src.split_to(end);
+            src.advance(1);
+
+            if encoded.is_empty() {
+                continue;
+            }
+            if encoded.len() > self.max_length {
+                return Err(too_long());
+            }
+
+            let mut frame = Vec::with_capacity(encoded.len());
+            let mut bytes = encoded.iter();
+
+            while let Some(&byte) = bytes.next() {
+                if byte != SLIP_ESC {
+                    frame.push(byte);
+                    continue;
+                }
+
+                match bytes.next() {
+                    Some(&SLIP_ESC_END) => frame.push(SLIP_END),
+                    Some(&SLIP_ESC_ESC) => frame.push(SLIP_ESC),
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Invalid SLIP escape sequence.",
+                        ))
+                    }
+                }
+            }
+
+            return Ok(Some(frame));
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for SlipCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: T, dst: &mut BytesMut) -> io::Result<()> {
+        let frame = frame.as_ref();
+
+        dst.reserve(frame.len() + 2);
+        dst.put_u8(SLIP_END);
+
+        for &byte in frame {
+            match byte {
+                SLIP_END => dst.put_slice(&[SLIP_ESC, SLIP_ESC_END]),
+                SLIP_ESC => dst.put_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+                byte => dst.put_u8(byte),
+            }
+        }
+
+        dst.put_u8(SLIP_END);
+
+        Ok(())
+    }
+}
+
+/// Consistent Overhead Byte Stuffing, with frames delimited by zero bytes.
+///
+/// Zero length frames get skipped when receiving.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CobsCodec {
+    max_length: usize,
+}
+
+impl CobsCodec {
+    /// Creates a COBS codec.
+    pub fn new() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_LENGTH,
+        }
+    }
+
+    /// Sets the maximum length of received frames in bytes, before decoding.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+}
+
+impl Default for CobsCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for CobsCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let Some(end) = src.iter().position(|&byte| byte == 0) else {
+                if src.len() > self.max_length {
+                    return Err(too_long());
+                }
+                return Ok(None);
+            };
+
+            let encoded = src.split_to(end);
+            src.advance(1);
+
+            if encoded.is_empty() {
+                continue;
+            }
+            if encoded.len() > self.max_length {
+                return Err(too_long());
+            }
+
+            return cobs_decode(&encoded).map(Some);
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for CobsCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: T, dst: &mut BytesMut) -> io::Result<()> {
+        let frame = frame.as_ref();
+
+        dst.reserve(frame.len() + frame.len() / 254 + 2);
+
+        // Every block starts with the distance to the next zero, at most 254 data bytes apart.
+        let mut blocks = frame.split(|&byte| byte == 0).peekable();
+
+        while let Some(block) = blocks.next() {
+            let full = block.len() / 254 * 254;
+
+            // Full chunks imply no zero.
+            for chunk in block[..full].chunks(254) {
+                dst.put_u8(0xff);
+                dst.put_slice(chunk);
+            }
+
+            // The rest ends with the zero before the next block, the last block needs no code for an empty rest.
+            let rest = &block[full..];
+            if !rest.is_empty() || full == 0 || blocks.peek().is_some() {
+                dst.put_u8(rest.len() as u8 + 1);
+                dst.put_slice(rest);
+            }
+        }
+
+        dst.put_u8(0);
+
+        Ok(())
+    }
+}
+
+fn cobs_decode(encoded: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid COBS frame.");
+
+    let mut frame = Vec::with_capacity(encoded.len());
+    let mut position = 0;
+
+    while position < encoded.len() {
+        let code = encoded[position] as usize;
+        let data = encoded
+            .get(position + 1..position + code)
+            .ok_or_else(invalid)?;
+
+        frame.extend_from_slice(data);
+        position += code;
+
+        if code < 0xff && position < encoded.len() {
+            frame.push(0);
+        }
+    }
+
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the bytes one at a time, as if every byte arrived with its own read.
+    fn decode_bytewise<D: Decoder<Item = Vec<u8>, Error = io::Error>>(
+        codec: &mut D,
+        encoded: &[u8],
+    ) -> Vec<Vec<u8>> {
+        let mut src = BytesMut::new();
+        let mut frames = Vec::new();
+
+        for &byte in encoded {
+            src.put_u8(byte);
+            while let Some(frame) = codec.decode(&mut src).unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert!(src.is_empty());
+        frames
+    }
+
+    fn encode<E: Encoder<Vec<u8>, Error = io::Error>>(codec: &mut E, frame: &[u8]) -> Vec<u8> {
+        let mut dst = BytesMut::new();
+        codec.encode(frame.to_vec(), &mut dst).unwrap();
+        dst.to_vec()
+    }
+
+    #[test]
+    fn slip_escapes() {
+        let mut codec = SlipCodec::new();
+
+        assert_eq!(
+            encode(&mut codec, &[1, SLIP_END, 2, SLIP_ESC, 3]),
+            [
+                SLIP_END,
+                1,
+                SLIP_ESC,
+                SLIP_ESC_END,
+                2,
+                SLIP_ESC,
+                SLIP_ESC_ESC,
+                3,
+                SLIP_END
+            ]
+        );
+        assert_eq!(
+            decode_bytewise(
+                &mut codec,
+                &[
+                    SLIP_END,
+                    1,
+                    SLIP_ESC,
+                    SLIP_ESC_END,
+                    2,
+                    SLIP_ESC,
+                    SLIP_ESC_ESC,
+                    3,
+                    SLIP_END
+                ]
+            ),
+            [vec![1, SLIP_END, 2, SLIP_ESC, 3]]
+        );
+    }
+
+    #[test]
+    fn slip_escape_split_across_reads() {
+        let mut codec = SlipCodec::new();
+        let mut src = BytesMut::from(&[SLIP_END, 1, SLIP_ESC][..]);
+
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        src.put_slice(&[SLIP_ESC_END, SLIP_ESC]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        src.put_slice(&[SLIP_ESC_ESC, SLIP_END, 2]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some(vec![1, SLIP_END, SLIP_ESC])
+        );
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        assert_eq!(&src[..], [2]);
+    }
+
+    #[test]
+    fn slip_round_trip() {
+        let mut codec = SlipCodec::new();
+        let frames = [
+            vec![SLIP_END],
+            vec![SLIP_ESC],
+            vec![SLIP_ESC, SLIP_ESC_END, SLIP_END, SLIP_ESC_ESC],
+            (0..=255).collect(),
+        ];
+
+        let encoded: Vec<u8> = frames
+            .iter()
+            .flat_map(|frame| encode(&mut codec, frame))
+            .collect();
+
+        // The END bytes of back to back frames enclose empty frames, which get skipped.
+        assert_eq!(decode_bytewise(&mut codec, &encoded), frames);
+    }
+
+    #[test]
+    fn slip_rejects_broken_frames() {
+        let mut codec = SlipCodec::new();
+
+        // An escape right before the END.
+        let mut src = BytesMut::from(&[1, SLIP_ESC, SLIP_END][..]);
+        assert!(codec.decode(&mut src).is_err());
+
+        let mut src = BytesMut::from(&[SLIP_ESC, 1, SLIP_END][..]);
+        assert!(codec.decode(&mut src).is_err());
+
+        let mut codec = SlipCodec::new().with_max_length(4);
+        let mut src = BytesMut::from(&[1, 2, 3, 4][..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.put_u8(5);
+        assert!(codec.decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn cobs_reference_encodings() {
+        let mut codec = CobsCodec::new();
+        let ascending = |range: std::ops::RangeInclusive<u8>| range.collect::<Vec<u8>>();
+        let cases = [
+            (vec![0], vec![1, 1, 0]),
+            (vec![0, 0], vec![1, 1, 1, 0]),
+            (vec![0, 0x11, 0], vec![1, 2, 0x11, 1, 0]),
+            (vec![0x11, 0x22, 0, 0x33], vec![3, 0x11, 0x22, 2, 0x33, 0]),
+            (
+                vec![0x11, 0x22, 0x33, 0x44],
+                vec![5, 0x11, 0x22, 0x33, 0x44, 0],
+            ),
+            (vec![0x11, 0, 0, 0], vec![2, 0x11, 1, 1, 1, 0]),
+            (
+                ascending(1..=254),
+                [vec![0xff], ascending(1..=254), vec![0]].concat(),
+            ),
+            (
+                [vec![0], ascending(1..=254)].concat(),
+                [vec![1, 0xff], ascending(1..=254), vec![0]].concat(),
+            ),
+            (
+                ascending(1..=255),
+                [vec![0xff], ascending(1..=254), vec![2, 0xff, 0]].concat(),
+            ),
+            (
+                [ascending(2..=255), vec![0]].concat(),
+                [vec![0xff], ascending(2..=255), vec![1, 1, 0]].concat(),
+            ),
+            (
+                [ascending(3..=255), vec![0, 1]].concat(),
+                [vec![0xfe], ascending(3..=255), vec![2, 1, 0]].concat(),
+            ),
+        ];
+
+        for (frame, encoded) in cases {
+            assert_eq!(encode(&mut codec, &frame), encoded);
+            assert_eq!(decode_bytewise(&mut codec, &encoded), [frame]);
+        }
+    }
+
+    #[test]
+    fn cobs_round_trip_around_block_limit() {
+        let mut codec = CobsCodec::new();
+
+        for len in [253, 254, 255, 507, 508, 509] {
+            let block = vec![0x55; len];
+            let frames = [
+                block.clone(),
+                [block.clone(), vec![0]].concat(),
+                [vec![0], block.clone()].concat(),
+                [block.clone(), vec![0], block.clone()].concat(),
+            ];
+
+            for frame in frames {
+                let encoded = encode(&mut codec, &frame);
+                assert_eq!(encoded.iter().filter(|&&byte| byte == 0).count(), 1);
+                assert_eq!(decode_bytewise(&mut codec, &encoded), [frame]);
+            }
+        }
+    }
+
+    #[test]
+    fn cobs_split_across_reads() {
+        let mut codec = CobsCodec::new();
+        let mut src = BytesMut::from(&[0, 3, 0x11][..]);
+
+        // The leading delimiter ends an empty frame, which gets skipped.
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        src.put_slice(&[0x22, 2]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        src.put_slice(&[0x33, 0, 2]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some(vec![0x11, 0x22, 0, 0x33])
+        );
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        assert_eq!(&src[..], [2]);
+    }
+
+    #[test]
+    fn cobs_rejects_broken_frames() {
+        let mut codec = CobsCodec::new();
+
+        // The code points past the end of the frame.
+        let mut src = BytesMut::from(&[3, 0x11, 0][..]);
+        assert!(codec.decode(&mut src).is_err());
+
+        let mut codec = CobsCodec::new().with_max_length(4);
+        let mut src = BytesMut::from(&[5, 1, 2, 3][..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.put_u8(4);
+        assert!(codec.decode(&mut src).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+//! Modem control lines, break signalling and line error reporting of serial devices.
+
+use std::{
+    io,
+    os::fd::RawFd,
+    time::{Duration, Instant},
+};
+
+use crate::{timing::sleep_precise, WiringXError};
+
+use super::{termios, wait_readable, Uart};
+
+/// Input line of the modem control interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ModemLine {
+    /// Clear to send.
+    Cts,
+    /// Data set ready.
+    Dsr,
+    /// Data carrier detect.
+    Dcd,
+    /// Ring indicator.
+    Ri,
+}
+
+impl ModemLine {
+    fn bit(&self) -> libc::c_int {
+        match self {
+            Self::Cts => libc::TIOCM_CTS,
+            Self::Dsr => libc::TIOCM_DSR,
+            Self::Dcd => libc::TIOCM_CD,
+            Self::Ri => libc::TIOCM_RI,
+        }
+    }
+}
+
+/// State of the modem control lines, true meaning asserted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModemStatus {
+    /// Data terminal ready, driven by this side.
+    pub dtr: bool,
+    /// Request to send, driven by this side.
+    pub rts: bool,
+    /// Clear to send.
+    pub cts: bool,
+    /// Data set ready.
+    pub dsr: bool,
+    /// Data carrier detect.
+    pub dcd: bool,
+    /// Ring indicator.
+    pub ri: bool,
+}
+
+impl ModemStatus {
+    fn from_bits(bits: libc::c_int) -> Self {
+        Self {
+            dtr: bits & libc::TIOCM_DTR != 0,
+            rts: bits & libc::TIOCM_RTS != 0,
+            cts: bits & libc::TIOCM_CTS != 0,
+            dsr: bits & libc::TIOCM_DSR != 0,
+            dcd: bits & libc::TIOCM_CD != 0,
+            ri: bits & libc::TIOCM_RI != 0,
+        }
+    }
+}
+
+/// Counters of the serial driver since the device was opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LineCounters {
+    /// Received bytes.
+    pub rx: u32,
+    /// Sent bytes.
+    pub tx: u32,
+    /// Received breaks.
+    pub breaks: u32,
+    /// Bytes received with a framing error.
+    pub framing_errors: u32,
+    /// Bytes received with a parity error.
+    pub parity_errors: u32,
+    /// Bytes lost because the hardware buffer was full.
+    pub overruns: u32,
+    /// Bytes lost because the kernel buffer was full.
+    pub buffer_overruns: u32,
+}
+
+/// `struct serial_icounter_struct` of the kernel.
+#[repr(C)]
+#[derive(Default)]
+struct SerialIcounter {
+    cts: libc::c_int,
+    dsr: libc::c_int,
+    rng: libc::c_int,
+    dcd: libc::c_int,
+    rx: libc::c_int,
+    tx: libc::c_int,
+    frame: libc::c_int,
+    overrun: libc::c_int,
+    parity: libc::c_int,
+    brk: libc::c_int,
+    buf_overrun: libc::c_int,
+    reserved: [libc::c_int; 9],
+}
+
+/// State of line error marking, kept between calls.
+#[derive(Debug, Default)]
+pub(super) struct LineMarking {
+    /// Input flags from before marking was enabled, restored when it gets disabled.
+    saved_iflag: Option<libc::tcflag_t>,
+    /// Bytes of an incomplete mark, finished by the next call of `read_event`.
+    pending: Vec<u8>,
+}
+
+/// Something received on a UART with line error marking enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEvent {
+    /// A byte received without errors.
+    Data(u8),
+    /// The line was held low for longer than a frame.
+    Break,
+    /// A byte received with a framing or parity error.
+    Error(u8),
+}
+
+impl Uart {
+    /// Drives the data terminal ready line.
+    pub fn set_dtr(&self, asserted: bool) -> Result<(), WiringXError> {
+        set_lines(self.fd, libc::TIOCM_DTR, asserted).map_err(WiringXError::Io)
+    }
+
+    /// Drives the request to send line.
+    pub fn set_rts(&self, asserted: bool) -> Result<(), WiringXError> {
+        set_lines(self.fd, libc::TIOCM_RTS, asserted).map_err(WiringXError::Io)
+    }
+
+    /// Returns the current state of the modem control lines.
+    pub fn modem_status(&self) -> Result<ModemStatus, WiringXError> {
+        let mut bits: libc::c_int = 0;
+
+        let result = unsafe { libc::ioctl(self.fd, libc::TIOCMGET as _, &mut bits) };
+
+        if result < 0 {
+            return Err(WiringXError::Io(io::Error::last_os_error()));
+        }
+
+        Ok(ModemStatus::from_bits(bits))
+    }
+
+    /// Blocks until one of the given input lines changes and returns the new state of all lines.
+    ///
+    /// Not all serial drivers support waiting for line changes, those return an `Io` error.
+    pub fn wait_for_modem_change(&self, lines: &[ModemLine]) -> Result<ModemStatus, WiringXError> {
+        let mask = lines.iter().fold(0, |mask, line| mask | line.bit());
+
+        let result = unsafe { libc::ioctl(self.fd, libc::TIOCMIWAIT as _, mask as libc::c_ulong) };
+
+        if result < 0 {
+            return Err(WiringXError::Io(io::Error::last_os_error()));
+        }
+
+        self.modem_status()
+    }
+
+    /// Holds the transmit line low for the given duration, after all pending output was sent.
+    ///
+    /// Used to start LIN frames or to get the attention of devices waiting for a break.
+    pub fn send_break(&self, duration: Duration) -> Result<(), WiringXError> {
+        if unsafe { libc::tcdrain(self.fd) } < 0 {
+            return Err(WiringXError::Io(io::Error::last_os_error()));
+        }
+
+        if unsafe { libc::ioctl(self.fd, libc::TIOCSBRK as _) } < 0 {
+            return Err(WiringXError::Io(io::Error::last_os_error()));
+        }
+
+        sleep_precise(duration);
+
+        if unsafe { libc::ioctl(self.fd, libc::TIOCCBRK as _) } < 0 {
+            return Err(WiringXError::Io(io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the counters of the serial driver, including received breaks and line errors.
+    pub fn line_counters(&self) -> Result<LineCounters, WiringXError> {
+        let mut counter = SerialIcounter::default();
+
+        let result = unsafe { libc::ioctl(self.fd, libc::TIOCGICOUNT as _, &mut counter) };
+
+        if result < 0 {
+            return Err(WiringXError::Io(io::Error::last_os_error()));
+        }
+
+        Ok(LineCounters {
+            rx: counter.rx as u32,
+            tx: counter.tx as u32,
+            breaks: counter.brk as u32,
+            framing_errors: counter.frame as u32,
+            parity_errors: counter.parity as u32,
+            overruns: counter.overrun as u32,
+            buffer_overruns: counter.buf_overrun as u32,
+        })
+    }
+
+    /// Makes the kernel mark received breaks and bytes with framing or parity errors in the input.
+    ///
+    /// While enabled the input has to be read with `read_event`, which decodes the marks. Without marking, breaks
+    /// and broken bytes show up as regular bytes. Disabling restores the input flags from before marking was enabled.
+    pub fn set_line_error_marking(&self, enabled: bool) -> Result<(), WiringXError> {
+        let mut marking = self.marking.lock();
+        let mut settings = termios::get(self.fd).map_err(WiringXError::Io)?;
+        let previous = settings.c_iflag;
+
+        if enabled {
+            settings.c_iflag |= libc::PARMRK | libc::INPCK;
+            settings.c_iflag &= !(libc::IGNBRK | libc::BRKINT | libc::IGNPAR | libc::ISTRIP);
+        } else {
+            match marking.saved_iflag {
+                Some(saved) => settings.c_iflag = saved,
+                None => settings.c_iflag &= !libc::PARMRK,
+            }
+        }
+
+        termios::set(self.fd, &settings, libc::TCSANOW).map_err(WiringXError::Io)?;
+
+        if enabled {
+            marking.saved_iflag.get_or_insert(previous);
+        } else {
+            marking.saved_iflag = None;
+            marking.pending.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next byte, break or broken byte, waiting up to the timeout. See `set_line_error_marking`.
+    ///
+    /// Returns `None` on timeout. A break is marked like a broken zero byte by the kernel, so a zero byte with a
+    /// framing error is reported as break as well. Marks cut short by the timeout get finished by the next call.
+    pub fn read_event(&self, timeout: Duration) -> Result<Option<LineEvent>, WiringXError> {
+        let deadline = Instant::now() + timeout;
+        let mut marking = self.marking.lock();
+
+        loop {
+            if let Some(event) = decode_event(&mut marking.pending) {
+                return Ok(Some(event));
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Some(byte) = read_byte(self.fd, remaining).map_err(WiringXError::Io)? else {
+                return Ok(None);
+            };
+
+            marking.pending.push(byte);
+        }
+    }
+}
+
+/// Takes the next event off the received bytes, if they hold a complete one.
+fn decode_event(pending: &mut Vec<u8>) -> Option<LineEvent> {
+    let (event, len) = match pending.as_slice() {
+        [] | [0xff] | [0xff, 0] => return None,
+        [0xff, 0xff, ..] => (LineEvent::Data(0xff), 2),
+        [0xff, 0, 0, ..] => (LineEvent::Break, 3),
+        [0xff, 0, byte, ..] => (LineEvent::Error(*byte), 3),
+        // Not a mark, the byte following the 0xff gets decoded on its own.
+        [0xff, ..] => (LineEvent::Data(0xff), 1),
+        [byte, ..] => (LineEvent::Data(*byte), 1),
+    };
+
+    pending.drain(..len);
+
+    Some(event)
+}
+
+/// Asserts or clears the given modem control lines.
+fn set_lines(fd: RawFd, bits: libc::c_int, asserted: bool) -> io::Result<()> {
+    let request = if asserted {
+        libc::TIOCMBIS
+    } else {
+        libc::TIOCMBIC
+    };
+
+    if unsafe { libc::ioctl(fd, request as _, &bits) } < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads a single byte, waiting up to the timeout.
+fn read_byte(fd: RawFd, timeout: Duration) -> io::Result<Option<u8>> {
+    if !wait_readable(fd, timeout)? {
+        return Ok(None);
+    }
+
+    let mut byte = 0u8;
+    let read = unsafe { libc::read(fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+
+    match read {
+        read if read < 0 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        _ => Ok(Some(byte)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_marks() {
+        let mut pending = vec![b'a', 0xff, 0xff, 0xff, 0, 0, 0xff, 0, b'x', 0xff, b'b'];
+        let mut events = Vec::new();
+
+        while let Some(event) = decode_event(&mut pending) {
+            events.push(event);
+        }
+
+        assert_eq!(
+            events,
+            [
+                LineEvent::Data(b'a'),
+                LineEvent::Data(0xff),
+                LineEvent::Break,
+                LineEvent::Error(b'x'),
+                LineEvent::Data(0xff),
+                LineEvent::Data(b'b'),
+            ]
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn keeps_incomplete_marks() {
+        let mut pending = vec![0xff];
+        assert_eq!(decode_event(&mut pending), None);
+
+        pending.push(0);
+        assert_eq!(decode_event(&mut pending), None);
+        assert_eq!(pending, [0xff, 0]);
+
+        pending.push(b'x');
+        assert_eq!(decode_event(&mut pending), Some(LineEvent::Error(b'x')));
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+//! Discovery of the serial ports of the system.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{WiringXError, WIRINGX};
+
+/// Directory containing the device nodes.
+const DEV_DIR: &str = "/dev";
+/// Directory of the persistent links named after USB serial numbers.
+const BY_ID_DIR: &str = "/dev/serial/by-id";
+/// Directory of the tty devices in sysfs.
+const SYSFS_TTY_DIR: &str = "/sys/class/tty";
+/// Directories UUCP style lock files get created in.
+const LOCK_DIRS: [&str; 2] = ["/var/lock", "/run/lock"];
+
+/// Name prefixes of the device nodes listed.
+const PORT_PREFIXES: [&str; 4] = ["ttyS", "ttyAMA", "ttyUSB", "ttyACM"];
+
+/// A serial port of the system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialPortInfo {
+    /// Path of the device node, for example `/dev/ttyUSB0`.
+    pub path: PathBuf,
+    /// Persistent link to the device in `/dev/serial/by-id`, if any.
+    pub by_id: Option<PathBuf>,
+    /// Name of the kernel driver, for example `ftdi_sio` or `serial8250`.
+    pub driver: Option<String>,
+    /// USB device the port belongs to, if any.
+    pub usb: Option<UsbInfo>,
+    /// Whether the port is open in this process through `WiringX::setup_uart`.
+    pub open: bool,
+    /// Process ID of another process holding a lock file for the port.
+    pub locked_by: Option<u32>,
+}
+
+/// USB device of a serial port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbInfo {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+/// Lists the serial ports of the system, ordered by path.
+///
+/// Covers `/dev/ttyS*`, `/dev/ttyAMA*`, `/dev/ttyUSB*`, `/dev/ttyACM*` and all devices linked in `/dev/serial/by-id`.
+/// Ports without a device behind them in sysfs get skipped, although UARTs of the 8250 driver are listed for every
+/// configured port, whether wired or not.
+pub fn available_ports() -> Result<Vec<SerialPortInfo>, WiringXError> {
+    let by_id = by_id_links();
+    let open = open_ports();
+
+    let mut paths: Vec<PathBuf> = by_id.keys().cloned().collect();
+
+    for entry in fs::read_dir(DEV_DIR).map_err(WiringXError::Io)? {
+        let entry = entry.map_err(WiringXError::Io)?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+
+        let is_port = PORT_PREFIXES.iter().any(|prefix| {
+            name.strip_prefix(prefix).is_some_and(|number| {
+                !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit())
+            })
+        });
+
+        if is_port && !paths.contains(&entry.path()) {
+            paths.push(entry.path());
+        }
+    }
+
+    let mut ports: Vec<SerialPortInfo> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?.to_string();
+            let device = Path::new(SYSFS_TTY_DIR).join(&name).join("device");
+
+            if !device.exists() {
+                return None;
+            }
+
+            Some(SerialPortInfo {
+                by_id: by_id.get(&path).cloned(),
+                driver: driver(&device),
+                usb: usb_info(&device),
+                open: open.contains(&path),
+                locked_by: lock_owner(&name),
+                path,
+            })
+        })
+        .collect();
+
+    ports.sort_by_key(|port| sort_key(&port.path));
+
+    Ok(ports)
+}
+
+/// Splits the trailing number off the path, so `ttyS10` comes after `ttyS2`.
+fn sort_key(path: &Path) -> (String, u64) {
+    let path = path.to_string_lossy();
+    let prefix = path.trim_end_matches(|char: char| char.is_ascii_digit());
+
+    (
+        prefix.to_string(),
+        path[prefix.len()..].parse().unwrap_or(0),
+    )
+}
+
+/// Maps the device nodes to their links in `/dev/serial/by-id`.
+fn by_id_links() -> HashMap<PathBuf, PathBuf> {
+    let Ok(entries) = fs::read_dir(BY_ID_DIR) else {
+        return HashMap::new();
+    };
+
+    entries
+        .filter_map(|entry| {
+            let link = entry.ok()?.path();
+            Some((fs::canonicalize(&link).ok()?, link))
+        })
+        .collect()
+}
+
+/// Returns the device nodes of the UARTs open in this process, with links resolved.
+fn open_ports() -> Vec<PathBuf> {
+    let Some(wiringx) = WIRINGX.get() else {
+        return Vec::new();
+    };
+
+    wiringx
+        .uart_handles
+        .lock()
+        .iter()
+        .map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+        .collect()
+}
+
+fn driver(device: &Path) -> Option<String> {
+    let driver = fs::read_link(device.join("driver")).ok()?;
+
+    Some(driver.file_name()?.to_str()?.to_string())
+}
+
+/// Walks up from the tty device to the USB device it belongs to.
+fn usb_info(device: &Path) -> Option<UsbInfo> {
+    let device = fs::canonicalize(device).ok()?;
+    let usb = device
+        .ancestors()
+        .find(|ancestor| ancestor.join("idVendor").exists())?;
+
+    let read = |name| -> Option<String> {
+        let value = fs::read_to_string(usb.join(name)).ok()?;
+        Some(value.trim().to_string()).filter(|value| !value.is_empty())
+    };
+    let id = |name| u16::from_str_radix(&read(name)?, 16).ok();
+
+    Some(UsbInfo {
+        vendor_id: id("idVendor")?,
+        product_id: id("idProduct")?,
+        serial_number: read("serial"),
+        manufacturer: read("manufacturer"),
+        product: read("product"),
+    })
+}
+
+/// Returns the process holding a lock file for the port, if it is another one and still alive.
+fn lock_owner(name: &str) -> Option<u32> {
+    LOCK_DIRS.iter().find_map(|dir| {
+        let content = fs::read(Path::new(dir).join(format!("LCK..{name}"))).ok()?;
+        let pid = lock_pid(&content)?;
+
+        (pid != 0 && pid != std::process::id() && process_alive(pid)).then_some(pid)
+    })
+}
+
+/// Parses the process ID of a lock file, written as text or as a binary integer by old programs.
+fn lock_pid(content: &[u8]) -> Option<u32> {
+    if let Some(pid) = std::str::from_utf8(content)
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+    {
+        return Some(pid);
+    }
+
+    Some(u32::from_ne_bytes(content.try_into().ok()?))
+}
+
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    let result = unsafe { libc::kill(pid, 0) };
+
+    // Processes of other users can not be signalled, but exist nonetheless.
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
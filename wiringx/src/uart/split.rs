@@ -0,0 +1,201 @@
+//! Independent reading and writing halves of a UART.
+
+use std::{
+    io::{self, BufRead, Read, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+use super::{poll_timeout, write_all, Uart};
+
+/// Size of the buffers of both halves.
+const BUFFER_SIZE: usize = 1024;
+
+impl Uart {
+    /// Splits the UART into a reading and a writing half, which can be moved to different threads.
+    ///
+    /// Each half has its own buffer. The device gets closed once both halves are dropped.
+    pub fn split(self) -> (UartReader, UartWriter) {
+        let uart = Arc::new(self);
+
+        let reader = UartReader {
+            uart: uart.clone(),
+            buffer: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            position: 0,
+            filled: 0,
+            timeout: None,
+        };
+
+        let writer = UartWriter {
+            uart,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+        };
+
+        (reader, writer)
+    }
+}
+
+/// Reading half of a UART, created by `Uart::split`.
+///
+/// Implements `Read` and `BufRead`, so lines can be read with `BufRead::read_line`.
+#[derive(Debug)]
+pub struct UartReader {
+    uart: Arc<Uart>,
+    buffer: Box<[u8]>,
+    position: usize,
+    filled: usize,
+    timeout: Option<Duration>,
+}
+
+impl UartReader {
+    /// Returns the UART, for example to read the modem control lines.
+    pub fn uart(&self) -> &Uart {
+        &self.uart
+    }
+
+    /// Sets how long reads wait for data before failing with `TimedOut`. `None` waits forever, which is the default.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the number of received bytes waiting in the buffer of this half.
+    pub fn buffered(&self) -> usize {
+        self.filled - self.position
+    }
+
+    /// Waits for data and reads it into the buffer, which has to be empty.
+    fn fill(&mut self) -> io::Result<()> {
+        let timeout = self.timeout.map_or(-1, poll_timeout);
+
+        loop {
+            let mut poll_fd = libc::pollfd {
+                fd: self.uart.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            let result = unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+
+            if result < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+
+            if result == 0 {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            let read = unsafe {
+                libc::read(
+                    self.uart.fd,
+                    self.buffer.as_mut_ptr() as *mut libc::c_void,
+                    self.buffer.len(),
+                )
+            };
+
+            if read < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+
+            // A hang up reports readable without data.
+            if read == 0 && poll_fd.revents & libc::POLLHUP == 0 {
+                continue;
+            }
+
+            self.position = 0;
+            self.filled = read as usize;
+
+            return Ok(());
+        }
+    }
+}
+
+impl Read for UartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+impl BufRead for UartReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position == self.filled {
+            self.fill()?;
+        }
+
+        Ok(&self.buffer[self.position..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = (self.position + amt).min(self.filled);
+    }
+}
+
+/// Writing half of a UART, created by `Uart::split`.
+///
+/// Writes get collected in a buffer, which gets sent when full, on `flush` and when the writer is dropped.
+#[derive(Debug)]
+pub struct UartWriter {
+    uart: Arc<Uart>,
+    buffer: Vec<u8>,
+}
+
+impl UartWriter {
+    /// Returns the UART, for example to drive the modem control lines.
+    pub fn uart(&self) -> &Uart {
+        &self.uart
+    }
+
+    /// Flushes the buffer and waits until all bytes have left the UART.
+    pub fn drain(&mut self) -> io::Result<()> {
+        self.flush()?;
+
+        if unsafe { libc::tcdrain(self.uart.fd) } < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Write for UartWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() + buf.len() > BUFFER_SIZE {
+            self.flush()?;
+        }
+
+        // Large writes bypass the buffer.
+        if buf.len() >= BUFFER_SIZE {
+            write_all(self.uart.fd, buf)?;
+        } else {
+            self.buffer.extend_from_slice(buf);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = write_all(self.uart.fd, &self.buffer);
+        self.buffer.clear();
+
+        result
+    }
+}
+
+impl Drop for UartWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
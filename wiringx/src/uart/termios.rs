@@ -0,0 +1,185 @@
+//! Terminal settings of serial devices.
+
+use std::{io, mem::MaybeUninit, os::fd::RawFd};
+
+use super::{FlowControl, Parity, SerialConfig, StopBits};
+
+/// Baud rates supported by termios with their speed constants.
+const BAUD_RATES: [(u32, libc::speed_t); 30] = [
+    (50, libc::B50),
+    (75, libc::B75),
+    (110, libc::B110),
+    (134, libc::B134),
+    (150, libc::B150),
+    (200, libc::B200),
+    (300, libc::B300),
+    (600, libc::B600),
+    (1200, libc::B1200),
+    (1800, libc::B1800),
+    (2400, libc::B2400),
+    (4800, libc::B4800),
+    (9600, libc::B9600),
+    (19200, libc::B19200),
+    (38400, libc::B38400),
+    (57600, libc::B57600),
+    (115200, libc::B115200),
+    (230400, libc::B230400),
+    (460800, libc::B460800),
+    (500000, libc::B500000),
+    (576000, libc::B576000),
+    (921600, libc::B921600),
+    (1000000, libc::B1000000),
+    (1152000, libc::B1152000),
+    (1500000, libc::B1500000),
+    (2000000, libc::B2000000),
+    (2500000, libc::B2500000),
+    (3000000, libc::B3000000),
+    (3500000, libc::B3500000),
+    (4000000, libc::B4000000),
+];
+
+/// Returns the termios speed constant of the baud rate.
+pub(crate) fn baud_constant(baud_rate: u32) -> Option<libc::speed_t> {
+    BAUD_RATES
+        .iter()
+        .find(|(rate, _)| *rate == baud_rate)
+        .map(|(_, speed)| *speed)
+}
+
+/// Returns the baud rate of the termios speed constant.
+fn baud_rate(speed: libc::speed_t) -> Option<u32> {
+    BAUD_RATES
+        .iter()
+        .find(|(_, constant)| *constant == speed)
+        .map(|(rate, _)| *rate)
+}
+
+/// Reads the terminal settings of the given file descriptor.
+pub(crate) fn get(fd: RawFd) -> io::Result<libc::termios> {
+    let mut termios = MaybeUninit::uninit();
+
+    if unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { termios.assume_init() })
+}
+
+/// Applies the terminal settings to the given file descriptor, `when` being one of the `TCSA*` constants.
+pub(crate) fn set(fd: RawFd, termios: &libc::termios, when: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(fd, when, termios) } < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Writes the baud rate and framing of the configuration into the terminal settings.
+///
+/// The configuration has to be checked beforehand.
+pub(crate) fn configure(termios: &mut libc::termios, config: &SerialConfig) -> io::Result<()> {
+    let speed = baud_constant(config.baud_rate)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Unsupported baud rate."))?;
+
+    if unsafe { libc::cfsetispeed(termios, speed) } < 0
+        || unsafe { libc::cfsetospeed(termios, speed) } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+
+    termios.c_cflag &= !libc::CSIZE;
+    termios.c_cflag |= match config.data_bits {
+        5 => libc::CS5,
+        6 => libc::CS6,
+        7 => libc::CS7,
+        _ => libc::CS8,
+    };
+
+    termios.c_cflag &= !(libc::PARENB | libc::PARODD | libc::CMSPAR);
+    termios.c_cflag |= match config.parity {
+        Parity::None => 0,
+        Parity::Even => libc::PARENB,
+        Parity::Odd => libc::PARENB | libc::PARODD,
+        Parity::Mark => libc::PARENB | libc::CMSPAR | libc::PARODD,
+        Parity::Space => libc::PARENB | libc::CMSPAR,
+    };
+
+    if config.parity == Parity::None {
+        termios.c_iflag &= !libc::INPCK;
+    } else {
+        termios.c_iflag |= libc::INPCK;
+    }
+
+    // With 5 data bits the UART sends 1.5 stop bits instead of 2.
+    match config.stop_bits {
+        StopBits::One => termios.c_cflag &= !libc::CSTOPB,
+        StopBits::OneAndHalf | StopBits::Two => termios.c_cflag |= libc::CSTOPB,
+    }
+
+    match config.flow_control {
+        FlowControl::None => termios.c_iflag &= !(libc::IXON | libc::IXOFF | libc::IXANY),
+        FlowControl::XOnOff => termios.c_iflag |= libc::IXON | libc::IXOFF | libc::IXANY,
+    }
+
+    Ok(())
+}
+
+/// Reads the baud rate and framing back from the terminal settings.
+pub(crate) fn config(termios: &libc::termios) -> io::Result<SerialConfig> {
+    let speed = unsafe { libc::cfgetospeed(termios) };
+    let baud_rate = baud_rate(speed)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown baud rate constant."))?;
+
+    let data_bits = match termios.c_cflag & libc::CSIZE {
+        libc::CS5 => 5,
+        libc::CS6 => 6,
+        libc::CS7 => 7,
+        _ => 8,
+    };
+
+    let cflag = termios.c_cflag;
+    let parity = if cflag & libc::PARENB == 0 {
+        Parity::None
+    } else {
+        match (cflag & libc::CMSPAR != 0, cflag & libc::PARODD != 0) {
+            (false, false) => Parity::Even,
+            (false, true) => Parity::Odd,
+            (true, true) => Parity::Mark,
+            (true, false) => Parity::Space,
+        }
+    };
+
+    let stop_bits = match (cflag & libc::CSTOPB != 0, data_bits) {
+        (false, _) => StopBits::One,
+        (true, 5) => StopBits::OneAndHalf,
+        (true, _) => StopBits::Two,
+    };
+
+    let flow_control = if termios.c_iflag & libc::IXON != 0 {
+        FlowControl::XOnOff
+    } else {
+        FlowControl::None
+    };
+
+    Ok(SerialConfig {
+        baud_rate,
+        data_bits,
+        parity,
+        stop_bits,
+        flow_control,
+    })
+}
+
+/// Switches the stick parity bit between mark and space once all pending output was sent.
+pub(crate) fn set_stick_parity(fd: RawFd, mark: bool) -> io::Result<()> {
+    let mut termios = get(fd)?;
+
+    termios.c_cflag |= libc::PARENB | libc::CMSPAR;
+    if mark {
+        termios.c_cflag |= libc::PARODD;
+    } else {
+        termios.c_cflag &= !libc::PARODD;
+    }
+
+    set(fd, &termios, libc::TCSADRAIN)
+}